@@ -0,0 +1,531 @@
+use bevy::prelude::*;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+};
+
+pub const ARENA_TILE_SIZE: f32 = 64.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArenaTile {
+    Grass,
+    Clearing,
+    Road,
+    Tree,
+    Wall,
+}
+
+impl ArenaTile {
+    pub fn is_walkable(&self) -> bool {
+        !matches!(self, ArenaTile::Tree | ArenaTile::Wall)
+    }
+
+    /// How costly it is to march across this tile. Roads are quicker than open ground.
+    pub fn travel_cost(&self) -> u32 {
+        match self {
+            ArenaTile::Road => 1,
+            _ => 2,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            ArenaTile::Grass => Color::WHITE,
+            ArenaTile::Clearing => Color::rgb(1.0, 1.0, 0.8),
+            ArenaTile::Road => Color::rgb(0.8, 0.6, 0.4),
+            ArenaTile::Tree => Color::rgb(0.2, 0.45, 0.2),
+            ArenaTile::Wall => Color::rgb(0.5, 0.5, 0.55),
+        }
+    }
+}
+
+/// A side of the arena that enemy waves can march in from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArenaEdge {
+    Bottom,
+}
+
+/// The edges enemy waves march in from.
+pub const SPAWN_EDGES: [ArenaEdge; 1] = [ArenaEdge::Bottom];
+
+/// Small deterministic RNG, so that the same seed always produces the same arena.
+struct ArenaRng(u64);
+
+impl ArenaRng {
+    fn next_u64(&mut self) -> u64 {
+        // splitmix64
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, max: usize) -> usize {
+        (self.next_u64() % max.max(1) as u64) as usize
+    }
+
+    fn in_range(&mut self, min: usize, max: usize) -> usize {
+        min + self.below(max - min)
+    }
+
+    fn chance(&mut self, p: f32) -> bool {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32 <= p
+    }
+}
+
+pub struct ArenaLayout {
+    pub width: usize,
+    pub height: usize,
+    pub tiles: Vec<ArenaTile>,
+    pub spawn: (usize, usize),
+    pub road_entry: (usize, usize),
+}
+
+impl ArenaLayout {
+    pub fn get(&self, x: usize, y: usize) -> ArenaTile {
+        self.tiles[y * self.width + x]
+    }
+
+    fn set(&mut self, x: usize, y: usize, tile: ArenaTile) {
+        self.tiles[y * self.width + x] = tile;
+    }
+
+    /// The world position of the bottom left corner of the arena.
+    pub fn origin(&self) -> Vec2 {
+        Vec2::new(
+            -(self.width as f32 * ARENA_TILE_SIZE) / 2.0,
+            -(self.height as f32 * ARENA_TILE_SIZE) / 2.0,
+        )
+    }
+
+    pub fn tile_to_world(&self, x: usize, y: usize) -> Vec2 {
+        self.origin() + (Vec2::new(x as f32, y as f32) + Vec2::splat(0.5)) * ARENA_TILE_SIZE
+    }
+
+    pub fn world_to_tile(&self, pos: Vec2) -> Option<(usize, usize)> {
+        let local = (pos - self.origin()) / ARENA_TILE_SIZE;
+        if local.x < 0.0 || local.y < 0.0 {
+            return None;
+        }
+        let (x, y) = (local.x as usize, local.y as usize);
+        if x < self.width && y < self.height {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+
+    /// The tile closest to a world position, which may lie outside of the arena.
+    pub fn nearest_tile(&self, pos: Vec2) -> (usize, usize) {
+        let local = ((pos - self.origin()) / ARENA_TILE_SIZE).max(Vec2::ZERO);
+        (
+            (local.x as usize).min(self.width - 1),
+            (local.y as usize).min(self.height - 1),
+        )
+    }
+
+    /// Whether a world position can be walked on. Anything outside of the arena is walkable.
+    pub fn is_walkable_at(&self, pos: Vec2) -> bool {
+        self.world_to_tile(pos)
+            .map_or(true, |(x, y)| self.get(x, y).is_walkable())
+    }
+
+    pub fn edge_tiles(&self, edge: ArenaEdge) -> Vec<(usize, usize)> {
+        match edge {
+            ArenaEdge::Bottom => (0..self.width).map(|x| (x, 0)).collect(),
+        }
+    }
+
+    /// The walkable tiles around the given one, along with the length of the step to them.
+    /// Diagonal steps can't cut the corner of an obstacle.
+    fn steps_from(&self, x: usize, y: usize) -> Vec<((usize, usize), u32)> {
+        let walkable = |dx: isize, dy: isize| {
+            let (nx, ny) = (x as isize + dx, y as isize + dy);
+            nx >= 0
+                && ny >= 0
+                && (nx as usize) < self.width
+                && (ny as usize) < self.height
+                && self.get(nx as usize, ny as usize).is_walkable()
+        };
+        let mut steps = Vec::with_capacity(8);
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let diagonal = dx != 0 && dy != 0;
+                if (dx, dy) == (0, 0)
+                    || !walkable(dx, dy)
+                    || (diagonal && !(walkable(dx, 0) && walkable(0, dy)))
+                {
+                    continue;
+                }
+                let tile = ((x as isize + dx) as usize, (y as isize + dy) as usize);
+                steps.push((tile, if diagonal { 14 } else { 10 }));
+            }
+        }
+        steps
+    }
+
+    /// Flood fills the walkable tiles reachable from the spawn point.
+    pub fn reachable_from_spawn(&self) -> Vec<bool> {
+        let mut reached = vec![false; self.tiles.len()];
+        let mut queue = VecDeque::new();
+        if self.get(self.spawn.0, self.spawn.1).is_walkable() {
+            reached[self.spawn.1 * self.width + self.spawn.0] = true;
+            queue.push_back(self.spawn);
+        }
+        while let Some((x, y)) = queue.pop_front() {
+            let neighbours = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for (nx, ny) in neighbours {
+                if nx < self.width
+                    && ny < self.height
+                    && !reached[ny * self.width + nx]
+                    && self.get(nx, ny).is_walkable()
+                {
+                    reached[ny * self.width + nx] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        reached
+    }
+
+    /// Whether every walkable tile on the given edge can be reached from the spawn point,
+    /// and there is at least one of them.
+    pub fn is_connected_to(&self, edge: ArenaEdge) -> bool {
+        let reached = self.reachable_from_spawn();
+        let tiles = self.edge_tiles(edge);
+        tiles.iter().any(|(x, y)| reached[y * self.width + x])
+            && tiles
+                .iter()
+                .filter(|(x, y)| self.get(*x, *y).is_walkable())
+                .all(|(x, y)| reached[y * self.width + x])
+    }
+
+    /// Clears obstacles along a path from the given tile towards the spawn point,
+    /// stopping as soon as it joins an already reachable tile.
+    fn carve_towards_spawn(&mut self, from: (usize, usize), reached: &[bool]) {
+        let (mut x, mut y) = from;
+        loop {
+            if !self.get(x, y).is_walkable() {
+                self.set(x, y, ArenaTile::Grass);
+            }
+            if reached[y * self.width + x] || (x, y) == self.spawn {
+                break;
+            }
+            if x.abs_diff(self.spawn.0) >= y.abs_diff(self.spawn.1) {
+                x = if x < self.spawn.0 { x + 1 } else { x - 1 };
+            } else {
+                y = if y < self.spawn.1 { y + 1 } else { y - 1 };
+            }
+        }
+    }
+
+    fn fill_circle(&mut self, center: (usize, usize), radius: usize, tile: ArenaTile) {
+        let r = radius as isize;
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let (x, y) = (center.0 as isize + dx, center.1 as isize + dy);
+                if dx * dx + dy * dy <= r * r
+                    && x >= 0
+                    && y >= 0
+                    && (x as usize) < self.width
+                    && (y as usize) < self.height
+                {
+                    self.set(x as usize, y as usize, tile);
+                }
+            }
+        }
+    }
+}
+
+/// The cost of marching from every tile of an arena to the nearest of its goal tiles,
+/// which enemies follow downhill to find their way around obstacles.
+pub struct FlowField {
+    goals: Vec<(usize, usize)>,
+    costs: Vec<Option<u32>>,
+}
+
+impl FlowField {
+    pub fn towards(layout: &ArenaLayout, goals: &[(usize, usize)]) -> Self {
+        let mut costs = vec![None; layout.tiles.len()];
+        let mut queue = BinaryHeap::new();
+        for &(x, y) in goals {
+            if layout.get(x, y).is_walkable() {
+                costs[y * layout.width + x] = Some(0);
+                queue.push(Reverse((0, (x, y))));
+            }
+        }
+        while let Some(Reverse((cost, (x, y)))) = queue.pop() {
+            if matches!(costs[y * layout.width + x], Some(c) if c < cost) {
+                continue;
+            }
+            for ((nx, ny), step) in layout.steps_from(x, y) {
+                let next = cost + step * layout.get(x, y).travel_cost();
+                let known = &mut costs[ny * layout.width + nx];
+                if !matches!(*known, Some(c) if c <= next) {
+                    *known = Some(next);
+                    queue.push(Reverse((next, (nx, ny))));
+                }
+            }
+        }
+        FlowField {
+            goals: goals.to_vec(),
+            costs,
+        }
+    }
+
+    pub fn goals(&self) -> &[(usize, usize)] {
+        &self.goals
+    }
+
+    pub fn cost(&self, layout: &ArenaLayout, x: usize, y: usize) -> Option<u32> {
+        self.costs[y * layout.width + x]
+    }
+
+    /// The tile to head for next from a world position,
+    /// or `None` once the goal is close enough to make for directly.
+    pub fn next_tile(&self, layout: &ArenaLayout, pos: Vec2) -> Option<(usize, usize)> {
+        let inside = layout.world_to_tile(pos);
+        let (x, y) = inside.unwrap_or_else(|| layout.nearest_tile(pos));
+        let here = self.cost(layout, x, y);
+        match (inside, here) {
+            (_, Some(0)) => return None,
+            // Step into the arena before following the field
+            (None, Some(_)) => return Some((x, y)),
+            _ => {}
+        }
+        let (cost, tile) = layout
+            .steps_from(x, y)
+            .into_iter()
+            .filter_map(|(tile, _)| Some((self.cost(layout, tile.0, tile.1)?, tile)))
+            .filter(|(cost, _)| !matches!(here, Some(h) if *cost >= h))
+            .min_by_key(|(cost, _)| *cost)?;
+        if cost == 0 {
+            None
+        } else {
+            Some(tile)
+        }
+    }
+}
+
+/// The flow fields enemies march along.
+/// The one towards the lich is rebuilt as it moves, the others hold for the whole day.
+pub struct EnemyPaths {
+    pub to_player: FlowField,
+    pub to_phylactery: FlowField,
+    pub retreat: FlowField,
+}
+
+impl EnemyPaths {
+    pub fn new(layout: &ArenaLayout, phylactery: Vec2) -> Self {
+        EnemyPaths {
+            to_player: FlowField::towards(layout, &[layout.spawn]),
+            to_phylactery: FlowField::towards(layout, &[layout.nearest_tile(phylactery)]),
+            retreat: FlowField::towards(layout, &layout.edge_tiles(ArenaEdge::Bottom)),
+        }
+    }
+}
+
+/// Generates a battlefield of the given size, in tiles.
+/// The lich spawns in the middle, and is guaranteed to be connected to every edge in `spawn_edges`.
+pub fn generate_arena(
+    seed: u64,
+    width: usize,
+    height: usize,
+    spawn_edges: &[ArenaEdge],
+) -> ArenaLayout {
+    let mut rng = ArenaRng(seed);
+    let mut layout = ArenaLayout {
+        width,
+        height,
+        tiles: vec![ArenaTile::Grass; width * height],
+        spawn: (width / 2, height / 2),
+        road_entry: (width / 2, 0),
+    };
+
+    // Tree clusters
+    for _ in 0..(width * height / 40).max(1) {
+        let (cx, cy) = (rng.below(width), rng.below(height));
+        let radius = rng.in_range(1, 3) as isize;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let (x, y) = (cx as isize + dx, cy as isize + dy);
+                if dx * dx + dy * dy <= radius * radius
+                    && x >= 0
+                    && y >= 0
+                    && (x as usize) < width
+                    && (y as usize) < height
+                    && rng.chance(0.7)
+                {
+                    layout.set(x as usize, y as usize, ArenaTile::Tree);
+                }
+            }
+        }
+    }
+
+    // Ruined walls, with gaps where they have crumbled
+    for _ in 0..(width * height / 80).max(1) {
+        let (mut x, mut y) = (rng.below(width), rng.below(height));
+        let horizontal = rng.chance(0.5);
+        for _ in 0..rng.in_range(3, 7) {
+            if x >= width || y >= height {
+                break;
+            }
+            if !rng.chance(0.25) {
+                layout.set(x, y, ArenaTile::Wall);
+            }
+            if horizontal {
+                x += 1;
+            } else {
+                y += 1;
+            }
+        }
+    }
+
+    // Clearings, including one around the lich
+    for _ in 0..rng.in_range(2, 4) {
+        let center = (rng.below(width), rng.below(height));
+        let radius = rng.in_range(1, 3);
+        layout.fill_circle(center, radius, ArenaTile::Clearing);
+    }
+    layout.fill_circle(layout.spawn, 2, ArenaTile::Clearing);
+
+    // The road the enemy marches along, from the first spawn edge to the lich
+    if let Some(edge) = spawn_edges.first() {
+        let entries = layout.edge_tiles(*edge);
+        let (mut x, mut y) = entries[rng.in_range(entries.len() / 4, entries.len() * 3 / 4)];
+        layout.road_entry = (x, y);
+        while (x, y) != layout.spawn {
+            layout.set(x, y, ArenaTile::Road);
+            let towards_x = x.abs_diff(layout.spawn.0) > y.abs_diff(layout.spawn.1)
+                || (x != layout.spawn.0 && rng.chance(0.3));
+            if towards_x {
+                x = if x < layout.spawn.0 { x + 1 } else { x - 1 };
+            } else {
+                y = if y < layout.spawn.1 { y + 1 } else { y - 1 };
+            }
+        }
+    }
+
+    // Make sure no part of a spawn edge is cut off from the lich
+    for edge in spawn_edges {
+        let tiles = layout.edge_tiles(*edge);
+        let (mx, my) = tiles[tiles.len() / 2];
+        if !layout.get(mx, my).is_walkable() {
+            layout.set(mx, my, ArenaTile::Grass);
+        }
+        for (x, y) in tiles {
+            let reached = layout.reachable_from_spawn();
+            if layout.get(x, y).is_walkable() && !reached[y * width + x] {
+                layout.carve_towards_spawn((x, y), &reached);
+            }
+        }
+    }
+    debug_assert!(spawn_edges.iter().all(|e| layout.is_connected_to(*e)));
+
+    layout
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::{WORLD_HEIGHT, WORLD_WIDTH},
+        phylactery::PHYLACTERY_POSITION,
+    };
+
+    fn world_sized_arena(seed: u64, spawn_edges: &[ArenaEdge]) -> ArenaLayout {
+        generate_arena(
+            seed,
+            (WORLD_WIDTH / ARENA_TILE_SIZE).ceil() as usize,
+            (WORLD_HEIGHT / ARENA_TILE_SIZE).ceil() as usize,
+            spawn_edges,
+        )
+    }
+
+    #[test]
+    fn same_seed_generates_same_arena() {
+        for seed in 0..50 {
+            let first = world_sized_arena(seed, &SPAWN_EDGES);
+            let second = world_sized_arena(seed, &SPAWN_EDGES);
+            assert_eq!(first.tiles, second.tiles);
+            assert_eq!(first.spawn, second.spawn);
+            assert_eq!(first.road_entry, second.road_entry);
+        }
+    }
+
+    #[test]
+    fn different_seeds_generate_different_arenas() {
+        let first = world_sized_arena(1, &SPAWN_EDGES);
+        assert!((2..10)
+            .map(|seed| world_sized_arena(seed, &SPAWN_EDGES).tiles)
+            .any(|tiles| tiles != first.tiles));
+    }
+
+    #[test]
+    fn spawn_and_phylactery_are_walkable() {
+        for seed in 0..200 {
+            let layout = world_sized_arena(seed, &SPAWN_EDGES);
+            assert!(layout.get(layout.spawn.0, layout.spawn.1).is_walkable());
+            assert!(layout.is_walkable_at(Vec2::ZERO));
+            assert!(layout.is_walkable_at(PHYLACTERY_POSITION.truncate()));
+        }
+    }
+
+    #[test]
+    fn flow_fields_lead_from_spawn_edges_to_the_lich() {
+        for seed in 0..50 {
+            let layout = world_sized_arena(seed, &SPAWN_EDGES);
+            let field = FlowField::towards(&layout, &[layout.spawn]);
+            for edge in SPAWN_EDGES {
+                for (x, y) in layout.edge_tiles(edge) {
+                    if !layout.get(x, y).is_walkable() {
+                        continue;
+                    }
+                    let mut tile = (x, y);
+                    let mut steps = 0;
+                    while let Some(next) =
+                        field.next_tile(&layout, layout.tile_to_world(tile.0, tile.1))
+                    {
+                        assert!(layout.get(next.0, next.1).is_walkable());
+                        tile = next;
+                        steps += 1;
+                        assert!(
+                            steps < layout.tiles.len(),
+                            "seed {} loops from {:?}",
+                            seed,
+                            (x, y)
+                        );
+                    }
+                    assert!(
+                        field.cost(&layout, tile.0, tile.1).unwrap()
+                            <= 14 * ArenaTile::Grass.travel_cost(),
+                        "seed {} stops short from {:?}",
+                        seed,
+                        (x, y)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spawn_edges_are_connected_to_spawn() {
+        for seed in 0..200 {
+            let layout = world_sized_arena(seed, &SPAWN_EDGES);
+            for edge in SPAWN_EDGES {
+                assert!(
+                    layout.is_connected_to(edge),
+                    "seed {} edge {:?}",
+                    seed,
+                    edge
+                );
+            }
+        }
+    }
+}
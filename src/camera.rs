@@ -0,0 +1,115 @@
+use crate::common::{
+    CameraFollow, DamagePlayerEvent, MainCamera, Player, ScreenShake, Settings, SCREEN_HEIGHT,
+    SCREEN_WIDTH, WORLD_HEIGHT, WORLD_WIDTH,
+};
+use bevy::{input::mouse::MouseWheel, prelude::*};
+
+const MIN_ZOOM: f32 = 0.75;
+const MAX_ZOOM: f32 = 1.5;
+/// How far the camera can be thrown by screen shake at full trauma and intensity.
+const MAX_SHAKE_OFFSET: f32 = 12.0;
+/// How much trauma wears off each second.
+const SHAKE_DECAY: f32 = 1.5;
+const HIT_TRAUMA: f32 = 0.4;
+
+pub fn setup_camera(mut commands: Commands) {
+    commands
+        .spawn_bundle(OrthographicCameraBundle::new_2d())
+        .insert(MainCamera)
+        .insert(CameraFollow {
+            target_zoom: 1.0,
+            shake: Vec2::ZERO,
+        });
+    commands.spawn_bundle(UiCameraBundle::default());
+}
+
+pub fn zoom_camera(
+    mut q_camera: Query<&mut CameraFollow, With<MainCamera>>,
+    mut scroll_events: EventReader<MouseWheel>,
+    keyboard_input: Res<Input<KeyCode>>,
+) {
+    if let Some(mut follow) = q_camera.iter_mut().next() {
+        let mut zoom_change: f32 = scroll_events.iter().map(|e| -e.y * 0.1).sum();
+        if keyboard_input.just_pressed(KeyCode::Equals) {
+            zoom_change -= 0.25;
+        }
+        if keyboard_input.just_pressed(KeyCode::Minus) {
+            zoom_change += 0.25;
+        }
+        follow.target_zoom = (follow.target_zoom + zoom_change).clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+}
+
+/// Rattles the camera whenever the lich gets hurt.
+pub fn shake_on_player_damage(
+    mut shake: ResMut<ScreenShake>,
+    mut damage_events: EventReader<DamagePlayerEvent>,
+) {
+    let hits = damage_events.iter().count();
+    if hits > 0 {
+        shake.trauma = (shake.trauma + HIT_TRAUMA * hits as f32).min(1.0);
+    }
+}
+
+/// Smoothly moves the camera towards the lich, without showing anything past the edge of the world.
+/// Screen shake is laid over the top, scaled by the intensity picked in the settings.
+#[allow(clippy::type_complexity)]
+pub fn follow_player(
+    mut q_camera: Query<
+        (
+            &mut Transform,
+            &mut OrthographicProjection,
+            &mut CameraFollow,
+        ),
+        (With<MainCamera>, Without<Player>),
+    >,
+    q_player: Query<&Transform, With<Player>>,
+    mut shake: ResMut<ScreenShake>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    if let (Some((mut transform, mut projection, mut follow)), Some(player)) =
+        (q_camera.iter_mut().next(), q_player.iter().next())
+    {
+        let smoothing = 1.0 - (-5.0 * time.delta_seconds()).exp();
+
+        let zoom = projection.scale + (follow.target_zoom - projection.scale) * smoothing;
+        if (zoom - projection.scale).abs() > f32::EPSILON {
+            projection.scale = zoom;
+        }
+
+        let half_view = Vec2::new(SCREEN_WIDTH, SCREEN_HEIGHT) * projection.scale / 2.0;
+        let max_offset = (Vec2::new(WORLD_WIDTH, WORLD_HEIGHT) / 2.0 - half_view).max(Vec2::ZERO);
+        let current = transform.translation.truncate() - follow.shake;
+        let target = (current + (player.translation.truncate() - current) * smoothing)
+            .clamp(-max_offset, max_offset);
+
+        // Squaring the trauma keeps light hits subtle and heavy ones violent
+        shake.trauma = (shake.trauma - SHAKE_DECAY * time.delta_seconds()).max(0.0);
+        let strength = shake.trauma.powi(2) * settings.screen_shake * MAX_SHAKE_OFFSET;
+        follow.shake =
+            Vec2::new(alea::f32_in_range(-1.0, 1.0), alea::f32_in_range(-1.0, 1.0)) * strength;
+
+        transform.translation.x = target.x + follow.shake.x;
+        transform.translation.y = target.y + follow.shake.y;
+    }
+}
+
+/// Moves the camera back to the middle of the world, for the menus.
+pub fn reset_camera(
+    mut q_camera: Query<(
+        &mut Transform,
+        &mut OrthographicProjection,
+        &mut CameraFollow,
+    )>,
+    mut shake: ResMut<ScreenShake>,
+) {
+    for (mut transform, mut projection, mut follow) in q_camera.iter_mut() {
+        transform.translation.x = 0.0;
+        transform.translation.y = 0.0;
+        projection.scale = 1.0;
+        follow.target_zoom = 1.0;
+        follow.shake = Vec2::ZERO;
+    }
+    shake.trauma = 0.0;
+}
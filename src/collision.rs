@@ -0,0 +1,81 @@
+use crate::common::{
+    EnemyAttackHitEvent, EnemyContactEvent, GamePhysicsLayer, HitTarget, PlayerAttackHitEvent,
+};
+use bevy::prelude::*;
+use heron::prelude::*;
+
+const LAYERS: [GamePhysicsLayer; 6] = [
+    GamePhysicsLayer::Player,
+    GamePhysicsLayer::PlayerAttack,
+    GamePhysicsLayer::Enemy,
+    GamePhysicsLayer::EnemyAttack,
+    GamePhysicsLayer::Minion,
+    GamePhysicsLayer::Phylactery,
+];
+
+/// The layer a body belongs to. Every body is expected to be in exactly one group.
+fn group_of(layers: CollisionLayers) -> Option<GamePhysicsLayer> {
+    LAYERS
+        .iter()
+        .copied()
+        .find(|layer| layers.contains_group(*layer))
+}
+
+/// Reads every collision once, and sends a typed event for each kind of interaction
+/// based on the pair of layers involved.
+pub fn route_collisions(
+    mut collision_events: EventReader<CollisionEvent>,
+    mut player_attack_writer: EventWriter<PlayerAttackHitEvent>,
+    mut enemy_contact_writer: EventWriter<EnemyContactEvent>,
+    mut enemy_attack_writer: EventWriter<EnemyAttackHitEvent>,
+) {
+    use GamePhysicsLayer::*;
+
+    for event in collision_events.iter() {
+        let (entity_1, entity_2) = event.rigid_body_entities();
+        let (layers_1, layers_2) = event.collision_layers();
+        let (group_1, group_2) = match (group_of(layers_1), group_of(layers_2)) {
+            (Some(group_1), Some(group_2)) => (group_1, group_2),
+            _ => continue,
+        };
+
+        // Try both orders, so that each interaction only has to be matched one way around
+        for ((first, e_first), (second, e_second)) in [
+            ((group_1, entity_1), (group_2, entity_2)),
+            ((group_2, entity_2), (group_1, entity_1)),
+        ] {
+            match (first, second) {
+                (PlayerAttack, Enemy) if event.is_started() => {
+                    player_attack_writer.send(PlayerAttackHitEvent {
+                        attack: e_first,
+                        enemy: e_second,
+                    });
+                }
+                (Enemy, Player) | (Enemy, Minion) | (Enemy, Phylactery) => {
+                    enemy_contact_writer.send(EnemyContactEvent {
+                        enemy: e_first,
+                        target: e_second,
+                        target_kind: match second {
+                            Player => HitTarget::Player,
+                            Minion => HitTarget::Minion,
+                            _ => HitTarget::Phylactery,
+                        },
+                        started: event.is_started(),
+                    });
+                }
+                (EnemyAttack, Player) | (EnemyAttack, Minion) if event.is_started() => {
+                    enemy_attack_writer.send(EnemyAttackHitEvent {
+                        attack: e_first,
+                        target: e_second,
+                        target_kind: if second == Player {
+                            HitTarget::Player
+                        } else {
+                            HitTarget::Minion
+                        },
+                    });
+                }
+                _ => (),
+            }
+        }
+    }
+}
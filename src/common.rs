@@ -1,21 +1,34 @@
-use std::time::Duration;
+use std::{collections::BTreeMap, time::Duration};
 
 use bevy::prelude::*;
 use bevy_asset_loader::AssetCollection;
 use bevy_kira_audio::AudioSource;
 use heron::prelude::*;
+use serde::{Deserialize, Serialize};
 
 pub const SCREEN_WIDTH: f32 = 960.0;
 pub const SCREEN_HEIGHT: f32 = 720.0;
 
+pub const WORLD_WIDTH: f32 = SCREEN_WIDTH * 2.0;
+pub const WORLD_HEIGHT: f32 = SCREEN_HEIGHT * 2.0;
+
+/// How far a stick has to be pushed before it counts as input.
+pub const STICK_DEADZONE: f32 = 0.25;
+
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
 pub enum GameState {
     AssetLoading,
     MainMenu,
     Opening,
     MoraleStatus,
+    Shop,
     ActiveGame,
+    /// Pushed on top of `ActiveGame`, which is left frozen underneath.
+    Paused,
     GameOver,
+    Controls,
+    /// Set from the main menu, or pushed on top of `Paused`.
+    Settings,
     Credits,
 }
 
@@ -81,6 +94,7 @@ pub struct DamagePlayerEvent(pub f32);
 pub enum DayEndReason {
     Timeout,
     PlayerDeath,
+    PhylacteryDestroyed,
 }
 
 pub struct EndDayEvent {
@@ -89,45 +103,278 @@ pub struct EndDayEvent {
 
 pub struct ChangeSpellEvent(pub PlayerSpell);
 
+pub struct MinionCommandEvent(pub MinionCommand);
+
+/// Which of the enemy's targets was involved in a collision.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HitTarget {
+    Player,
+    Minion,
+    Phylactery,
+}
+
+/// A player attack started touching an enemy.
+#[derive(Clone, Copy)]
+pub struct PlayerAttackHitEvent {
+    pub attack: Entity,
+    pub enemy: Entity,
+}
+
+/// An enemy started or stopped touching one of its targets.
+#[derive(Clone, Copy)]
+pub struct EnemyContactEvent {
+    pub enemy: Entity,
+    pub target: Entity,
+    pub target_kind: HitTarget,
+    pub started: bool,
+}
+
+/// An enemy projectile started touching one of its targets.
+#[derive(Clone, Copy)]
+pub struct EnemyAttackHitEvent {
+    pub attack: Entity,
+    pub target: Entity,
+    pub target_kind: HitTarget,
+}
+
+/// Where a hit on an enemy came from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DamageSource {
+    /// The lich's own spells, the only damage its upgrades make stronger.
+    Spell,
+    Minion,
+    /// A status effect hurting the enemy over time.
+    StatusEffect,
+}
+
+/// A single hit on an enemy, before its resistances are taken into account.
+pub struct DamageEnemyEvent {
+    pub enemy: Entity,
+    pub damage: f32,
+    pub damage_type: DamageType,
+    pub effect: Option<(StatusEffectKind, f32)>,
+    pub source: DamageSource,
+}
+
 // Components
 
 #[derive(Component)]
 pub struct MainCamera;
 
+#[derive(Component)]
+pub struct CameraFollow {
+    pub target_zoom: f32,
+    /// How far screen shake has knocked the camera off the lich this frame.
+    pub shake: Vec2,
+}
+
 #[derive(Component)]
 pub struct Player;
 
+/// The vessel holding the lich's soul. If it is destroyed, the run is over.
+#[derive(Component)]
+pub struct Phylactery {
+    /// Enemies currently touching the phylactery, hurting it every `contact_tick`.
+    pub contacts: Vec<Entity>,
+    pub contact_tick: Timer,
+}
+
+/// Ignores all incoming damage until the timer runs out.
+#[derive(Component)]
+pub struct Invulnerable(pub Timer);
+
+/// The lich has been struck down and is reforming at its phylactery.
+#[derive(Component)]
+pub struct Reforming(pub Timer);
+
+#[derive(Clone, Copy)]
 pub enum EnemyAI {
     ChasesPlayer { speed: f32 },
+    RaidsPhylactery { speed: f32 },
     Archer { target_y: f32 },
     Afraid { speed: f32 },
 }
 
+/// Tougher kinds of soldiers, which shrug off some kinds of harm.
+#[derive(Clone, Copy, PartialEq)]
+pub enum EnemyVariant {
+    Regular,
+    Armored,
+    Priest,
+}
+
+impl EnemyVariant {
+    pub fn resistances(&self) -> Resistances {
+        match self {
+            EnemyVariant::Regular => Resistances::default(),
+            EnemyVariant::Armored => Resistances {
+                fire: 0.75,
+                lightning: -0.25,
+                physical: 0.5,
+                ..Default::default()
+            },
+            EnemyVariant::Priest => Resistances {
+                shadow: 0.5,
+                fear: true,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            EnemyVariant::Regular => Color::WHITE,
+            EnemyVariant::Armored => Color::rgb(0.7, 0.75, 0.9),
+            EnemyVariant::Priest => Color::rgb(1.0, 0.95, 0.6),
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct Enemy {
     pub ai: EnemyAI,
+    pub variant: EnemyVariant,
     pub wave_core: Option<Entity>,
     pub fear_threshold: f32,
+    /// The behaviour to return to once a temporary terror wears off.
+    pub calm_ai: Option<EnemyAI>,
+}
+
+impl Enemy {
+    /// Makes the enemy flee. A temporary fright remembers what the enemy was doing,
+    /// so that it can return to it once the fright wears off.
+    pub fn frighten(&mut self, temporary: bool) {
+        if let EnemyAI::Afraid { speed: _ } = self.ai {
+            if !temporary {
+                self.calm_ai = None;
+            }
+            return;
+        }
+        let speed = match self.ai {
+            EnemyAI::ChasesPlayer { speed } | EnemyAI::RaidsPhylactery { speed } => speed,
+            _ => 120.0,
+        };
+        self.calm_ai = if temporary { Some(self.ai) } else { None };
+        self.ai = EnemyAI::Afraid { speed };
+    }
 }
 
 #[derive(Component)]
 pub struct EnemyShoots(pub Timer);
 
+/// Marks an enemy whose killing blow was dealt by a minion, rather than the lich.
+#[derive(Component)]
+pub struct KilledByMinion;
+
+/// The remains of a fallen soldier, which can be raised as the given kind of minion.
+#[derive(Component)]
+pub struct Corpse(pub MinionKind);
+
+#[derive(Clone, Copy)]
+pub enum MinionKind {
+    Skeleton,
+    Ghoul,
+}
+
+impl MinionKind {
+    pub fn resistances(&self) -> Resistances {
+        match self {
+            // Arrows and blades mostly slip between bare bones
+            MinionKind::Skeleton => Resistances {
+                physical: 0.25,
+                ..Default::default()
+            },
+            MinionKind::Ghoul => Resistances::default(),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Minion {
+    pub speed: f32,
+    pub damage: f32,
+    pub attack: Timer,
+    pub hold_position: Vec2,
+    /// Enemies currently touching this minion, hurting it every `contact_tick`.
+    pub contacts: Vec<Entity>,
+    pub contact_tick: Timer,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MinionCommand {
+    Follow,
+    HoldPosition,
+    AttackNearestWave,
+    GuardPhylactery,
+}
+
+impl MinionCommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MinionCommand::Follow => "Follow",
+            MinionCommand::HoldPosition => "Hold Position",
+            MinionCommand::AttackNearestWave => "Attack",
+            MinionCommand::GuardPhylactery => "Guard Phylactery",
+        }
+    }
+}
+
 #[derive(Component)]
-pub struct EnemyProjectile;
+pub struct EnemyProjectile {
+    pub damage: f32,
+    pub damage_type: DamageType,
+}
+
+#[derive(Clone, Copy)]
+pub enum WaveKind {
+    Knights,
+    Archers,
+    Raiders,
+}
 
 #[derive(Component)]
 pub struct WaveCore {
     pub remaining: u32,
+    pub kind: WaveKind,
+}
+
+/// An arrow at the edge of the screen, or its enemy count, pointing at an off-screen wave.
+#[derive(Component)]
+pub struct OffscreenIndicator {
+    pub wave_core: Entity,
+}
+
+pub enum MinimapMarkerKind {
+    Lich,
+    Soldier,
+    Archer,
+    /// A dot along the line between an archer and the lich, at the given fraction of the way.
+    FiringLine(f32),
+}
+
+#[derive(Component)]
+pub struct MinimapMarker {
+    pub target: Entity,
+    pub kind: MinimapMarkerKind,
 }
 
 #[derive(Component)]
 pub enum Ui {
     Core,
     HealthBarMain,
+    ManaBar,
+    PhylacteryHealthBar,
     TimeLeftDisplay,
     NarrationText,
     CurrentSpell,
+    SpellModifiers,
+    SoulsDisplay,
+    SpellUnlockIcon,
+    ChargeMeter,
+    ControlsHint,
+    ControlsStatus,
+    Minimap,
+    MinionOrders,
 }
 
 #[derive(Component)]
@@ -151,22 +398,247 @@ impl Health {
     }
 }
 
+#[derive(Component)]
+pub struct Mana {
+    pub current: f32,
+    pub maximum: f32,
+    /// Mana regenerated per second, before the bonus from souls harvested today.
+    pub regen: f32,
+}
+
+impl Mana {
+    pub fn full(mana: f32, regen: f32) -> Self {
+        Self {
+            current: mana,
+            maximum: mana,
+            regen,
+        }
+    }
+
+    /// Spends the given amount of mana if there is enough of it, returning whether it was spent.
+    pub fn try_spend(&mut self, cost: f32) -> bool {
+        if self.current >= cost {
+            self.current -= cost;
+            true
+        } else {
+            false
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct DamagesPlayer {
     pub damage: f32,
+    pub damage_type: DamageType,
     pub tick: Timer,
     pub is_damaging: bool,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DamageType {
+    Fire,
+    Lightning,
+    Shadow,
+    Physical,
+}
+
+/// How much of each type of damage an enemy or minion ignores, from 0 (none) to 1 (all).
+/// Negative values make it take extra damage instead.
+#[derive(Component, Clone, Copy, Default)]
+pub struct Resistances {
+    pub fire: f32,
+    pub lightning: f32,
+    pub shadow: f32,
+    pub physical: f32,
+    /// Whether the enemy can't be terrified.
+    pub fear: bool,
+}
+
+impl Resistances {
+    pub fn damage_taken(&self, damage: f32, damage_type: DamageType) -> f32 {
+        let resistance = match damage_type {
+            DamageType::Fire => self.fire,
+            DamageType::Lightning => self.lightning,
+            DamageType::Shadow => self.shadow,
+            DamageType::Physical => self.physical,
+        };
+        damage * (1.0 - resistance).max(0.0)
+    }
+
+    pub fn resists(&self, effect: StatusEffectKind) -> bool {
+        match effect {
+            StatusEffectKind::Terrified => self.fear,
+            StatusEffectKind::Burning => self.fire >= 0.75,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct DamagesEnemy {
     pub damage: f32,
-    pub induces_fear: bool,
+    pub damage_type: DamageType,
+    /// A status effect applied on hit, and how many seconds it lasts.
+    pub effect: Option<(StatusEffectKind, f32)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StatusEffectKind {
+    Burning,
+    Stunned,
+    Terrified,
+    Slowed,
+    Rooted,
+}
+
+impl StatusEffectKind {
+    pub fn max_stacks(&self) -> u32 {
+        match self {
+            StatusEffectKind::Burning => 3,
+            StatusEffectKind::Slowed => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn tint(&self) -> Color {
+        match self {
+            StatusEffectKind::Burning => Color::rgb(1.0, 0.6, 0.2),
+            StatusEffectKind::Stunned => Color::rgb(1.0, 1.0, 0.5),
+            StatusEffectKind::Terrified => Color::rgb(1.0, 0.5, 1.0),
+            StatusEffectKind::Slowed => Color::rgb(0.5, 0.7, 1.0),
+            StatusEffectKind::Rooted => Color::rgb(0.6, 0.8, 0.4),
+        }
+    }
+}
+
+/// How often status effects that hurt over time deal their damage, in seconds.
+pub const STATUS_EFFECT_TICK: f32 = 0.5;
+
+pub struct StatusEffect {
+    pub kind: StatusEffectKind,
+    pub stacks: u32,
+    pub duration: Timer,
+    /// Repeats every `STATUS_EFFECT_TICK` for as long as the effect lasts.
+    pub tick: Timer,
+}
+
+/// Timed effects on an enemy. Reapplying an effect adds a stack, up to its maximum,
+/// and refreshes its duration.
+#[derive(Component, Default)]
+pub struct StatusEffects(pub Vec<StatusEffect>);
+
+impl StatusEffects {
+    pub fn apply(&mut self, kind: StatusEffectKind, seconds: f32) {
+        if let Some(effect) = self.0.iter_mut().find(|e| e.kind == kind) {
+            effect.stacks = (effect.stacks + 1).min(kind.max_stacks());
+            if effect.duration.duration().as_secs_f32() - effect.duration.elapsed_secs() < seconds {
+                effect.duration = Timer::from_seconds(seconds, false);
+            }
+        } else {
+            self.0.push(StatusEffect {
+                kind,
+                stacks: 1,
+                duration: Timer::from_seconds(seconds, false),
+                tick: Timer::from_seconds(STATUS_EFFECT_TICK, true),
+            });
+        }
+    }
+
+    pub fn stacks(&self, kind: StatusEffectKind) -> u32 {
+        self.0
+            .iter()
+            .find(|e| e.kind == kind)
+            .map_or(0, |e| e.stacks)
+    }
+
+    pub fn has(&self, kind: StatusEffectKind) -> bool {
+        self.stacks(kind) > 0
+    }
+
+    /// Whether the enemy is currently unable to move.
+    pub fn is_held(&self) -> bool {
+        self.has(StatusEffectKind::Stunned) || self.has(StatusEffectKind::Rooted)
+    }
+
+    /// How much the enemy's speed is currently multiplied by.
+    pub fn speed_factor(&self) -> f32 {
+        0.5f32.powi(self.stacks(StatusEffectKind::Slowed) as i32)
+    }
 }
 
 #[derive(Component)]
 pub struct DespawnTimer(pub Timer);
 
+/// Changes how a player projectile behaves as it flies and when it hits an enemy.
+#[derive(Component, Clone, Copy, Default)]
+pub struct ProjectileModifiers {
+    /// How many enemies the projectile passes through before it is spent.
+    pub pierce: u32,
+    /// How many times the projectile bounces off the edges of the arena.
+    pub bounces: u32,
+    /// How many more times the projectile splits in two once it is spent.
+    pub splits: u32,
+    /// How quickly the projectile turns towards where the lich aims, in radians per second.
+    pub homing: f32,
+}
+
+impl ProjectileModifiers {
+    /// Unlocks the modifier earned for surviving the given day, and describes it.
+    pub fn unlock_for_day(&mut self, day: u32) -> &'static str {
+        match day % 4 {
+            1 => {
+                self.pierce += 1;
+                "Your fireballs now pierce through one more soldier."
+            }
+            2 => {
+                self.bounces += 1;
+                "Your fireballs now bounce off the edges of the battlefield."
+            }
+            3 => {
+                self.homing += 1.5;
+                "Your fireballs now seek out your cursor."
+            }
+            _ => {
+                self.splits += 1;
+                "Your fireballs now split apart when they are spent."
+            }
+        }
+    }
+
+    /// A short summary of every active modifier, to show next to the spell icon.
+    pub fn label(&self) -> String {
+        let mut parts = Vec::new();
+        if self.pierce > 0 {
+            parts.push(format!("P{}", self.pierce));
+        }
+        if self.bounces > 0 {
+            parts.push(format!("B{}", self.bounces));
+        }
+        if self.splits > 0 {
+            parts.push(format!("S{}", self.splits));
+        }
+        if self.homing > 0.0 {
+            parts.push("H".to_string());
+        }
+        parts.join(" ")
+    }
+}
+
+/// A player projectile that stops at the first enemies it hits, rather than passing through them.
+#[derive(Component, Default)]
+pub struct PlayerProjectile {
+    /// Every enemy this projectile, or the one it split from, has already hit.
+    pub hits: Vec<Entity>,
+    /// How many of `hits` were inherited from the projectile this one split from.
+    pub inherited_hits: usize,
+}
+
+impl PlayerProjectile {
+    pub fn own_hits(&self) -> u32 {
+        (self.hits.len() - self.inherited_hits) as u32
+    }
+}
+
 #[derive(Component)]
 pub struct InvisTimer(pub Timer);
 
@@ -179,6 +651,8 @@ pub struct Animated {
 #[derive(Component)]
 pub enum MainMenuButton {
     Start,
+    Controls,
+    Settings,
     Credits,
 }
 
@@ -188,27 +662,171 @@ pub enum GameOverButton {
     MainMenu,
 }
 
-#[derive(Clone, Copy)]
+/// Marks the button that Escape or B presses, to leave whichever menu it's in.
+#[derive(Component)]
+pub struct BackButton;
+
+#[derive(Component)]
+pub enum ControlsButton {
+    Rebind(Action, InputDevice),
+    ResetDefaults,
+    Back,
+}
+
+#[derive(Component)]
+pub enum PauseButton {
+    Resume,
+    Settings,
+    RestartDay,
+    QuitToMenu,
+}
+
+#[derive(Component)]
+pub enum SettingsButton {
+    Cycle(Setting),
+    Back,
+}
+
+#[derive(Component)]
+pub enum ShopButton {
+    Buy(Upgrade),
+    Learn(PlayerSpell),
+    StartDay,
+}
+
+/// Part of the spell bar along the bottom of the screen.
+#[derive(Component)]
+pub enum SpellBarElement {
+    /// The frame around an unlocked spell, highlighted while it is selected.
+    Slot(PlayerSpell),
+    Icon(PlayerSpell),
+    /// One of the dots ringing a slot, which light up in turn as the spell recovers.
+    Sweep {
+        spell: PlayerSpell,
+        index: usize,
+    },
+}
+
+/// Part of the targeting reticle that follows the cursor, shaped after the selected spell.
+#[derive(Component, Clone, Copy)]
+pub enum ReticlePart {
+    /// The area a spell lands in.
+    Ring,
+    /// The extra area caught only by a lightning strike's splash.
+    SplashRing,
+    /// How wide a fear wave is where it passes the cursor.
+    Strip,
+    /// One of the fireball paths, by how many steps it is rotated off the aim.
+    Spread(i32),
+}
+
+/// The ring texture used by the targeting reticle, drawn at startup.
+pub struct ReticleTexture(pub Handle<Image>);
+
+/// Shows off newly learned spells on the morale screen, before fading away.
+#[derive(Component)]
+pub struct SpellUnlockCutscene(pub Timer);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum PlayerSpell {
     Fireball,
     LightningStrike,
+    ChainLightning,
+    LightningStorm,
     FearWave,
+    RaiseDead,
 }
 
 impl PlayerSpell {
-    pub fn next(&self) -> Self {
+    /// Every spell, in the order they are cycled through.
+    pub const ALL: [PlayerSpell; 6] = [
+        PlayerSpell::Fireball,
+        PlayerSpell::LightningStrike,
+        PlayerSpell::ChainLightning,
+        PlayerSpell::LightningStorm,
+        PlayerSpell::FearWave,
+        PlayerSpell::RaiseDead,
+    ];
+
+    /// The next spell out of the `unlocked` ones, wrapping around.
+    pub fn next(&self, unlocked: &[PlayerSpell]) -> Self {
+        self.cycle(unlocked, 1)
+    }
+
+    /// The previous spell out of the `unlocked` ones, wrapping around.
+    pub fn previous(&self, unlocked: &[PlayerSpell]) -> Self {
+        self.cycle(unlocked, Self::ALL.len() - 1)
+    }
+
+    fn cycle(&self, unlocked: &[PlayerSpell], step: usize) -> Self {
+        let index = Self::ALL.iter().position(|s| s == self).unwrap();
+        (1..=Self::ALL.len())
+            .map(|i| Self::ALL[(index + i * step) % Self::ALL.len()])
+            .find(|s| unlocked.contains(s))
+            .unwrap_or(*self)
+    }
+
+    pub fn name(&self) -> &'static str {
         match self {
-            PlayerSpell::Fireball => PlayerSpell::LightningStrike,
-            PlayerSpell::LightningStrike => PlayerSpell::FearWave,
-            PlayerSpell::FearWave => PlayerSpell::Fireball,
+            PlayerSpell::Fireball => "Fireball",
+            PlayerSpell::LightningStrike => "Lightning Strike",
+            PlayerSpell::ChainLightning => "Chain Lightning",
+            PlayerSpell::LightningStorm => "Lightning Storm",
+            PlayerSpell::FearWave => "Fear Wave",
+            PlayerSpell::RaiseDead => "Raise Dead",
         }
     }
 
-    pub fn previous(&self) -> Self {
+    /// The day this spell is learned on, if it hasn't been bought before then.
+    pub fn unlock_day(&self) -> u32 {
         match self {
-            PlayerSpell::Fireball => PlayerSpell::FearWave,
-            PlayerSpell::LightningStrike => PlayerSpell::Fireball,
-            PlayerSpell::FearWave => PlayerSpell::LightningStrike,
+            PlayerSpell::Fireball => 1,
+            PlayerSpell::LightningStrike => 2,
+            PlayerSpell::FearWave => 3,
+            PlayerSpell::ChainLightning => 5,
+            PlayerSpell::RaiseDead => 6,
+            PlayerSpell::LightningStorm => 8,
+        }
+    }
+
+    /// How many souls it costs to learn this spell early in the shop.
+    pub fn soul_cost(&self) -> Option<u32> {
+        match self {
+            PlayerSpell::Fireball => None,
+            PlayerSpell::LightningStrike => Some(40),
+            PlayerSpell::FearWave => Some(60),
+            PlayerSpell::ChainLightning => Some(90),
+            PlayerSpell::RaiseDead => Some(100),
+            PlayerSpell::LightningStorm => Some(150),
+        }
+    }
+
+    pub fn mana_cost(&self) -> f32 {
+        match self {
+            PlayerSpell::Fireball => 4.0,
+            PlayerSpell::LightningStrike => 12.0,
+            PlayerSpell::ChainLightning => 18.0,
+            PlayerSpell::LightningStorm => 40.0,
+            PlayerSpell::FearWave => 15.0,
+            PlayerSpell::RaiseDead => 20.0,
+        }
+    }
+
+    /// The icon for this spell, and the color to tint it with.
+    pub fn icon(&self, sprites: &GameSprites) -> (Handle<Image>, Color) {
+        match self {
+            PlayerSpell::Fireball => (sprites.spell_icon_fireball.clone(), Color::WHITE),
+            PlayerSpell::LightningStrike => (sprites.spell_icon_lightning.clone(), Color::WHITE),
+            PlayerSpell::ChainLightning => (
+                sprites.spell_icon_lightning.clone(),
+                Color::rgb(0.6, 0.8, 1.0),
+            ),
+            PlayerSpell::LightningStorm => (
+                sprites.spell_icon_lightning.clone(),
+                Color::rgb(0.8, 0.6, 1.0),
+            ),
+            PlayerSpell::FearWave => (sprites.spell_icon_fear.clone(), Color::WHITE),
+            PlayerSpell::RaiseDead => (sprites.spell_icon_fear.clone(), Color::rgb(0.5, 1.0, 0.6)),
         }
     }
 }
@@ -216,7 +834,11 @@ impl PlayerSpell {
 pub struct SpellCooldowns {
     pub fireball: Timer,
     pub lightning_strike: Timer,
+    pub chain_lightning: Timer,
+    pub lightning_storm: Timer,
     pub fear_wave: Timer,
+    pub raise_dead: Timer,
+    pub blink: Timer,
 }
 
 impl Default for SpellCooldowns {
@@ -224,16 +846,53 @@ impl Default for SpellCooldowns {
         Self {
             fireball: Timer::from_seconds(0.3, false),
             lightning_strike: Timer::from_seconds(0.8, false),
+            chain_lightning: Timer::from_seconds(1.2, false),
+            lightning_storm: Timer::from_seconds(5.0, false),
             fear_wave: Timer::from_seconds(0.7, false),
+            raise_dead: Timer::from_seconds(1.5, false),
+            blink: Timer::from_seconds(2.0, false),
         }
     }
 }
 
 impl SpellCooldowns {
+    /// The default cooldowns, with every duration multiplied by `multiplier`.
+    pub fn scaled(multiplier: f32) -> Self {
+        let mut cooldowns = Self::default();
+        for timer in [
+            &mut cooldowns.fireball,
+            &mut cooldowns.lightning_strike,
+            &mut cooldowns.chain_lightning,
+            &mut cooldowns.lightning_storm,
+            &mut cooldowns.fear_wave,
+            &mut cooldowns.raise_dead,
+            &mut cooldowns.blink,
+        ] {
+            let duration = timer.duration().mul_f32(multiplier);
+            timer.set_duration(duration);
+        }
+        cooldowns
+    }
+
+    pub fn for_spell(&self, spell: PlayerSpell) -> &Timer {
+        match spell {
+            PlayerSpell::Fireball => &self.fireball,
+            PlayerSpell::LightningStrike => &self.lightning_strike,
+            PlayerSpell::ChainLightning => &self.chain_lightning,
+            PlayerSpell::LightningStorm => &self.lightning_storm,
+            PlayerSpell::FearWave => &self.fear_wave,
+            PlayerSpell::RaiseDead => &self.raise_dead,
+        }
+    }
+
     pub fn tick_all(&mut self, delta: Duration) {
         self.fireball.tick(delta);
         self.lightning_strike.tick(delta);
+        self.chain_lightning.tick(delta);
+        self.lightning_storm.tick(delta);
         self.fear_wave.tick(delta);
+        self.raise_dead.tick(delta);
+        self.blink.tick(delta);
     }
 }
 
@@ -243,11 +902,52 @@ pub struct PlayerSpellData {
     pub cooldowns: SpellCooldowns,
     pub no_shoot_delay: Timer,
     pub no_shoot_penalty: Timer,
+    /// Seconds the selected spell has been charged for, while the cast button is held.
+    pub charge: Option<f32>,
 }
 
-#[derive(Component)]
+/// A bolt falling from the sky, which blasts every enemy within `radius` once it lands.
+#[derive(Component, Clone)]
 pub struct LightningStrikeBolt {
     pub end_y: f32,
+    pub radius: f32,
+    pub damage: f32,
+    /// A weaker ring around the blast, as its extra radius and damage.
+    pub splash: Option<(f32, f32)>,
+    pub chain: Option<ChainLightning>,
+    pub effect: Option<(StatusEffectKind, f32)>,
+}
+
+impl LightningStrikeBolt {
+    pub fn strike(end_y: f32) -> Self {
+        Self {
+            end_y,
+            radius: 96.0,
+            damage: 3.0,
+            splash: Some((16.0, 2.0)),
+            chain: None,
+            effect: Some((StatusEffectKind::Stunned, 1.0)),
+        }
+    }
+}
+
+/// Arcs from the enemy closest to a bolt's impact to the nearest other enemies in turn,
+/// each jump dealing `falloff` times the damage of the previous one.
+#[derive(Clone)]
+pub struct ChainLightning {
+    pub jumps: u32,
+    pub range: f32,
+    pub falloff: f32,
+}
+
+/// Drops `strikes` bolts at random spots within `radius` of `center`, one every `interval`.
+#[derive(Component)]
+pub struct LightningStorm {
+    pub center: Vec2,
+    pub radius: f32,
+    pub strikes: u32,
+    pub interval: Timer,
+    pub bolt: LightningStrikeBolt,
 }
 
 // Resources
@@ -255,11 +955,741 @@ pub struct LightningStrikeBolt {
 #[derive(Component)]
 pub struct NarrationViewed(pub bool);
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Upgrade {
+    MaxHealth,
+    MaxMana,
+    SpellDamage,
+    CooldownReduction,
+}
+
+impl Upgrade {
+    pub const ALL: [Upgrade; 4] = [
+        Upgrade::MaxHealth,
+        Upgrade::MaxMana,
+        Upgrade::SpellDamage,
+        Upgrade::CooldownReduction,
+    ];
+    pub const MAX_LEVEL: u32 = 5;
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Upgrade::MaxHealth => "Hardened Bones",
+            Upgrade::MaxMana => "Deeper Well",
+            Upgrade::SpellDamage => "Dark Potency",
+            Upgrade::CooldownReduction => "Quickened Rites",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Upgrade::MaxHealth => "+25 max health",
+            Upgrade::MaxMana => "+20 max mana",
+            Upgrade::SpellDamage => "+15% spell damage",
+            Upgrade::CooldownReduction => "-10% spell cooldowns",
+        }
+    }
+
+    fn base_cost(&self) -> u32 {
+        match self {
+            Upgrade::MaxHealth | Upgrade::MaxMana => 30,
+            Upgrade::SpellDamage | Upgrade::CooldownReduction => 50,
+        }
+    }
+}
+
+/// Levels of every upgrade bought with souls so far.
+#[derive(Default, Clone, Copy)]
+pub struct Upgrades {
+    pub max_health: u32,
+    pub max_mana: u32,
+    pub spell_damage: u32,
+    pub cooldown_reduction: u32,
+}
+
+impl Upgrades {
+    pub fn level(&self, upgrade: Upgrade) -> u32 {
+        match upgrade {
+            Upgrade::MaxHealth => self.max_health,
+            Upgrade::MaxMana => self.max_mana,
+            Upgrade::SpellDamage => self.spell_damage,
+            Upgrade::CooldownReduction => self.cooldown_reduction,
+        }
+    }
+
+    fn level_mut(&mut self, upgrade: Upgrade) -> &mut u32 {
+        match upgrade {
+            Upgrade::MaxHealth => &mut self.max_health,
+            Upgrade::MaxMana => &mut self.max_mana,
+            Upgrade::SpellDamage => &mut self.spell_damage,
+            Upgrade::CooldownReduction => &mut self.cooldown_reduction,
+        }
+    }
+
+    /// How many souls the next level of an upgrade costs, or `None` if it is maxed out.
+    pub fn cost(&self, upgrade: Upgrade) -> Option<u32> {
+        let level = self.level(upgrade);
+        (level < Upgrade::MAX_LEVEL).then(|| upgrade.base_cost() * (level + 1))
+    }
+
+    pub fn max_health(&self) -> f32 {
+        200.0 + 25.0 * self.max_health as f32
+    }
+
+    pub fn max_mana(&self) -> f32 {
+        100.0 + 20.0 * self.max_mana as f32
+    }
+
+    pub fn damage_multiplier(&self) -> f32 {
+        1.0 + 0.15 * self.spell_damage as f32
+    }
+
+    pub fn cooldown_multiplier(&self) -> f32 {
+        0.9_f32.powi(self.cooldown_reduction as i32)
+    }
+}
+
+/// State that lasts for a whole run, from the start of day 0 until game over.
+pub struct RunState {
+    pub seed: u64,
+    pub modifiers: ProjectileModifiers,
+    /// Souls harvested from slain soldiers, spent in the shop between days.
+    pub souls: u32,
+    pub upgrades: Upgrades,
+    pub unlocked_spells: Vec<PlayerSpell>,
+}
+
+impl Default for RunState {
+    fn default() -> Self {
+        Self {
+            seed: alea::u64(),
+            modifiers: ProjectileModifiers::default(),
+            souls: 0,
+            upgrades: Upgrades::default(),
+            unlocked_spells: vec![PlayerSpell::Fireball],
+        }
+    }
+}
+
+impl RunState {
+    /// The seed used to generate the arena for a given day.
+    pub fn day_seed(&self, day: u32) -> u64 {
+        self.seed ^ (day as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+    }
+
+    /// Spends souls on the next level of an upgrade, if it can be afforded.
+    pub fn buy(&mut self, upgrade: Upgrade) -> bool {
+        match self.upgrades.cost(upgrade) {
+            Some(cost) if cost <= self.souls => {
+                self.souls -= cost;
+                *self.upgrades.level_mut(upgrade) += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Every unlocked spell, in the order they are cycled through.
+    pub fn known_spells(&self) -> Vec<PlayerSpell> {
+        PlayerSpell::ALL
+            .into_iter()
+            .filter(|s| self.unlocked_spells.contains(s))
+            .collect()
+    }
+
+    /// Spends souls to learn a spell early, if it can be afforded.
+    pub fn buy_spell(&mut self, spell: PlayerSpell) -> bool {
+        match spell.soul_cost() {
+            Some(cost) if cost <= self.souls && !self.unlocked_spells.contains(&spell) => {
+                self.souls -= cost;
+                self.unlocked_spells.push(spell);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Learns every spell due by the given day, returning the ones that weren't known yet.
+    pub fn unlock_spells_for_day(&mut self, day: u32) -> Vec<PlayerSpell> {
+        let new_spells = PlayerSpell::ALL
+            .into_iter()
+            .filter(|s| s.unlock_day() <= day && !self.unlocked_spells.contains(s))
+            .collect::<Vec<_>>();
+        self.unlocked_spells.extend(new_spells.iter().copied());
+        new_spells
+    }
+}
+
+/// What the day that just ended earned the lich, settled as it ends and shown on the morale screen.
+#[derive(Default)]
+pub struct DayReport {
+    pub souls_harvested: u32,
+    pub new_spells: Vec<PlayerSpell>,
+    pub modifier_unlocked: &'static str,
+}
+
+/// Optional rules that change how a run plays out.
+#[derive(Default)]
+pub struct GameRules {
+    /// Dying reforms the lich at its phylactery after a delay, instead of ending the day.
+    pub respawn_at_phylactery: bool,
+}
+
+/// One option on the settings screen, changed by cycling through its values.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    Fullscreen,
+    WindowScale,
+    VSync,
+    FrameCap,
+    ScreenShake,
+    Colorblind,
+    RespawnAtPhylactery,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 10] = [
+        Setting::MasterVolume,
+        Setting::MusicVolume,
+        Setting::SfxVolume,
+        Setting::Fullscreen,
+        Setting::WindowScale,
+        Setting::VSync,
+        Setting::FrameCap,
+        Setting::ScreenShake,
+        Setting::Colorblind,
+        Setting::RespawnAtPhylactery,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Setting::MasterVolume => "Master Volume",
+            Setting::MusicVolume => "Music Volume",
+            Setting::SfxVolume => "Sound Volume",
+            Setting::Fullscreen => "Fullscreen",
+            Setting::WindowScale => "Window Scale",
+            Setting::VSync => "VSync",
+            Setting::FrameCap => "Frame Cap",
+            Setting::ScreenShake => "Screen Shake",
+            Setting::Colorblind => "Colorblind Colors",
+            Setting::RespawnAtPhylactery => "Reform at Phylactery",
+        }
+    }
+}
+
+const WINDOW_SCALES: [f32; 4] = [1.0, 1.25, 1.5, 2.0];
+const FRAME_CAPS: [Option<u32>; 5] = [None, Some(30), Some(60), Some(120), Some(144)];
+const SHAKE_INTENSITIES: [f32; 4] = [0.0, 0.5, 1.0, 1.5];
+
+/// Picks whichever option comes after `current`, wrapping back around to the first.
+fn next_option<T: Copy + PartialEq>(options: &[T], current: T) -> T {
+    let index = options.iter().position(|o| *o == current).unwrap_or(0);
+    options[(index + 1) % options.len()]
+}
+
+/// Player preferences, saved to the settings config.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub fullscreen: bool,
+    pub window_scale: f32,
+    pub vsync: bool,
+    /// Frames per second to hold the game to. `None` leaves it uncapped.
+    pub frame_cap: Option<u32>,
+    pub screen_shake: f32,
+    pub colorblind: bool,
+    pub respawn_at_phylactery: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.0,
+            music_volume: 1.0,
+            sfx_volume: 1.0,
+            fullscreen: false,
+            window_scale: 1.0,
+            vsync: true,
+            frame_cap: None,
+            screen_shake: 1.0,
+            colorblind: false,
+            respawn_at_phylactery: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn cycle(&mut self, setting: Setting) {
+        // Volumes go up in tenths, then wrap back around to silent
+        let next_volume = |volume: f32| ((volume * 10.0).round() + 1.0) % 11.0 / 10.0;
+        match setting {
+            Setting::MasterVolume => self.master_volume = next_volume(self.master_volume),
+            Setting::MusicVolume => self.music_volume = next_volume(self.music_volume),
+            Setting::SfxVolume => self.sfx_volume = next_volume(self.sfx_volume),
+            Setting::Fullscreen => self.fullscreen = !self.fullscreen,
+            Setting::WindowScale => {
+                self.window_scale = next_option(&WINDOW_SCALES, self.window_scale)
+            }
+            Setting::VSync => self.vsync = !self.vsync,
+            Setting::FrameCap => self.frame_cap = next_option(&FRAME_CAPS, self.frame_cap),
+            Setting::ScreenShake => {
+                self.screen_shake = next_option(&SHAKE_INTENSITIES, self.screen_shake)
+            }
+            Setting::Colorblind => self.colorblind = !self.colorblind,
+            Setting::RespawnAtPhylactery => {
+                self.respawn_at_phylactery = !self.respawn_at_phylactery
+            }
+        }
+    }
+
+    pub fn value_label(&self, setting: Setting) -> String {
+        let on_off = |on: bool| if on { "On" } else { "Off" }.to_string();
+        let percent = |fraction: f32| format!("{}%", (fraction * 100.0).round());
+        match setting {
+            Setting::MasterVolume => percent(self.master_volume),
+            Setting::MusicVolume => percent(self.music_volume),
+            Setting::SfxVolume => percent(self.sfx_volume),
+            Setting::Fullscreen => on_off(self.fullscreen),
+            Setting::WindowScale => format!("{}x", self.window_scale),
+            Setting::VSync => on_off(self.vsync),
+            Setting::FrameCap => match self.frame_cap {
+                Some(fps) => format!("{} FPS", fps),
+                None => "None".to_string(),
+            },
+            Setting::ScreenShake if self.screen_shake == 0.0 => "Off".to_string(),
+            Setting::ScreenShake => percent(self.screen_shake),
+            Setting::Colorblind => on_off(self.colorblind),
+            Setting::RespawnAtPhylactery => on_off(self.respawn_at_phylactery),
+        }
+    }
+}
+
+/// The colors that tell things apart on the HUD, with a set that stays distinct for colorblind players.
+#[derive(Clone, Copy)]
+pub struct ColorPalette {
+    pub knights: Color,
+    pub archers: Color,
+    pub raiders: Color,
+    pub afraid: Color,
+    pub health_full: Color,
+    pub health: Color,
+    pub health_low: Color,
+}
+
+impl ColorPalette {
+    pub const STANDARD: ColorPalette = ColorPalette {
+        knights: Color::rgb(1.0, 0.3, 0.2),
+        archers: Color::rgb(1.0, 0.85, 0.2),
+        raiders: Color::rgb(0.8, 0.3, 1.0),
+        afraid: Color::rgb(1.0, 0.5, 1.0),
+        health_full: Color::CYAN,
+        health: Color::GREEN,
+        health_low: Color::RED,
+    };
+
+    /// Built on the Okabe-Ito colors, so no two of them hinge on telling red from green.
+    pub const COLORBLIND: ColorPalette = ColorPalette {
+        knights: Color::rgb(0.84, 0.37, 0.0),
+        archers: Color::rgb(0.94, 0.89, 0.26),
+        raiders: Color::rgb(0.0, 0.45, 0.7),
+        afraid: Color::rgb(0.8, 0.47, 0.65),
+        health_full: Color::rgb(0.34, 0.71, 0.91),
+        health: Color::rgb(0.0, 0.62, 0.45),
+        health_low: Color::rgb(0.9, 0.6, 0.0),
+    };
+}
+
+impl Default for ColorPalette {
+    fn default() -> Self {
+        ColorPalette::STANDARD
+    }
+}
+
+/// How shaken up the camera is, from 0 to 1. Wears off over time.
+#[derive(Default)]
+pub struct ScreenShake {
+    pub trauma: f32,
+}
+
+/// The gamepad the lich is played with, if one is plugged in.
+#[derive(Default)]
+pub struct ActiveGamepad(pub Option<Gamepad>);
+
+impl ActiveGamepad {
+    pub fn pressed(&self, buttons: &Input<GamepadButton>, button: GamepadButtonType) -> bool {
+        self.0.map_or(false, |gamepad| {
+            buttons.pressed(GamepadButton(gamepad, button))
+        })
+    }
+
+    pub fn just_pressed(&self, buttons: &Input<GamepadButton>, button: GamepadButtonType) -> bool {
+        self.0.map_or(false, |gamepad| {
+            buttons.just_pressed(GamepadButton(gamepad, button))
+        })
+    }
+
+    /// Reads a stick as a vector no longer than 1, resting at zero inside the deadzone.
+    pub fn stick(
+        &self,
+        axes: &Axis<GamepadAxis>,
+        x_axis: GamepadAxisType,
+        y_axis: GamepadAxisType,
+    ) -> Vec2 {
+        let gamepad = match self.0 {
+            Some(gamepad) => gamepad,
+            None => return Vec2::ZERO,
+        };
+        let stick = Vec2::new(
+            axes.get(GamepadAxis(gamepad, x_axis)).unwrap_or(0.0),
+            axes.get(GamepadAxis(gamepad, y_axis)).unwrap_or(0.0),
+        );
+        if stick.length() < STICK_DEADZONE {
+            Vec2::ZERO
+        } else {
+            stick.clamp_length_max(1.0)
+        }
+    }
+}
+
+/// Whichever kind of device the player touched last, so prompts can speak its language.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InputDevice {
+    KeyboardMouse,
+    Gamepad,
+}
+
+impl Default for InputDevice {
+    fn default() -> Self {
+        InputDevice::KeyboardMouse
+    }
+}
+
+/// Something the player can do, independent of whichever key or button does it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveLeft,
+    MoveDown,
+    MoveRight,
+    Cast,
+    Blink,
+    PreviousSpell,
+    NextSpell,
+    /// Picks a known spell directly, by its slot on the spell bar.
+    SelectSpell(usize),
+    MinionFollow,
+    MinionHold,
+    MinionAttack,
+    MinionGuard,
+}
+
+/// One spell bar slot per spell there is to learn.
+pub const SPELL_SLOTS: usize = PlayerSpell::ALL.len();
+const ACTION_COUNT: usize = Action::GENERAL.len() + SPELL_SLOTS + Action::MINION_COMMANDS.len();
+
+impl Action {
+    /// Every action, in the order they're listed on the controls screen.
+    pub const ALL: [Action; ACTION_COUNT] = Action::all();
+
+    const GENERAL: [Action; 8] = [
+        Action::MoveUp,
+        Action::MoveLeft,
+        Action::MoveDown,
+        Action::MoveRight,
+        Action::Cast,
+        Action::Blink,
+        Action::PreviousSpell,
+        Action::NextSpell,
+    ];
+
+    const fn all() -> [Action; ACTION_COUNT] {
+        let mut all = [Action::MoveUp; ACTION_COUNT];
+        let mut i = 0;
+        while i < Action::GENERAL.len() {
+            all[i] = Action::GENERAL[i];
+            i += 1;
+        }
+        let mut slot = 0;
+        while slot < SPELL_SLOTS {
+            all[i] = Action::SelectSpell(slot);
+            slot += 1;
+            i += 1;
+        }
+        let mut command = 0;
+        while command < Action::MINION_COMMANDS.len() {
+            all[i] = Action::MINION_COMMANDS[command];
+            command += 1;
+            i += 1;
+        }
+        all
+    }
+
+    pub const MINION_COMMANDS: [Action; 4] = [
+        Action::MinionFollow,
+        Action::MinionHold,
+        Action::MinionAttack,
+        Action::MinionGuard,
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            Action::MoveUp => "Move Up".to_string(),
+            Action::MoveLeft => "Move Left".to_string(),
+            Action::MoveDown => "Move Down".to_string(),
+            Action::MoveRight => "Move Right".to_string(),
+            Action::Cast => "Cast".to_string(),
+            Action::Blink => "Blink".to_string(),
+            Action::PreviousSpell => "Previous Spell".to_string(),
+            Action::NextSpell => "Next Spell".to_string(),
+            Action::SelectSpell(slot) => format!("Spell {}", slot + 1),
+            Action::MinionFollow => "Minions: Follow".to_string(),
+            Action::MinionHold => "Minions: Hold".to_string(),
+            Action::MinionAttack => "Minions: Attack".to_string(),
+            Action::MinionGuard => "Minions: Guard".to_string(),
+        }
+    }
+
+    /// Movement stays on the left stick, so it can't be given a gamepad button.
+    pub fn gamepad_bindable(&self) -> bool {
+        !matches!(
+            self,
+            Action::MoveUp | Action::MoveLeft | Action::MoveDown | Action::MoveRight
+        )
+    }
+
+    pub fn minion_command(&self) -> Option<MinionCommand> {
+        match self {
+            Action::MinionFollow => Some(MinionCommand::Follow),
+            Action::MinionHold => Some(MinionCommand::HoldPosition),
+            Action::MinionAttack => Some(MinionCommand::AttackNearestWave),
+            Action::MinionGuard => Some(MinionCommand::GuardPhylactery),
+            _ => None,
+        }
+    }
+}
+
+/// A key or mouse button an action can be bound to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum InputBinding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl InputBinding {
+    pub fn name(&self) -> String {
+        match self {
+            InputBinding::Key(key) => {
+                // Number keys are called Key1, Key2... which is more than a prompt needs
+                let name = format!("{:?}", key);
+                name.strip_prefix("Key").unwrap_or(&name).to_string()
+            }
+            InputBinding::Mouse(MouseButton::Left) => "LMB".to_string(),
+            InputBinding::Mouse(MouseButton::Right) => "RMB".to_string(),
+            InputBinding::Mouse(MouseButton::Middle) => "MMB".to_string(),
+            InputBinding::Mouse(MouseButton::Other(button)) => format!("Mouse {}", button),
+        }
+    }
+}
+
+/// Names gamepad buttons the way they're printed on an Xbox-style pad.
+pub fn gamepad_button_name(button: GamepadButtonType) -> String {
+    match button {
+        GamepadButtonType::South => "A".to_string(),
+        GamepadButtonType::East => "B".to_string(),
+        GamepadButtonType::West => "X".to_string(),
+        GamepadButtonType::North => "Y".to_string(),
+        GamepadButtonType::LeftTrigger => "LB".to_string(),
+        GamepadButtonType::RightTrigger => "RB".to_string(),
+        GamepadButtonType::LeftTrigger2 => "LT".to_string(),
+        GamepadButtonType::RightTrigger2 => "RT".to_string(),
+        GamepadButtonType::LeftThumb => "LS".to_string(),
+        GamepadButtonType::RightThumb => "RS".to_string(),
+        GamepadButtonType::Select => "Back".to_string(),
+        GamepadButtonType::DPadUp => "Up".to_string(),
+        GamepadButtonType::DPadDown => "Down".to_string(),
+        GamepadButtonType::DPadLeft => "Left".to_string(),
+        GamepadButtonType::DPadRight => "Right".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Which keys and buttons trigger each action, saved to the controls config.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InputMap {
+    pub keyboard: BTreeMap<Action, InputBinding>,
+    /// Moving and aiming are always on the sticks, so not every action has a gamepad button.
+    pub gamepad: BTreeMap<Action, GamepadButtonType>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let number_keys = [
+            KeyCode::Key1,
+            KeyCode::Key2,
+            KeyCode::Key3,
+            KeyCode::Key4,
+            KeyCode::Key5,
+            KeyCode::Key6,
+        ];
+        let mut keyboard = BTreeMap::from([
+            (Action::MoveUp, InputBinding::Key(KeyCode::W)),
+            (Action::MoveLeft, InputBinding::Key(KeyCode::A)),
+            (Action::MoveDown, InputBinding::Key(KeyCode::S)),
+            (Action::MoveRight, InputBinding::Key(KeyCode::D)),
+            (Action::Cast, InputBinding::Mouse(MouseButton::Left)),
+            (Action::Blink, InputBinding::Key(KeyCode::Space)),
+            (Action::PreviousSpell, InputBinding::Key(KeyCode::Q)),
+            (Action::NextSpell, InputBinding::Key(KeyCode::E)),
+            (Action::MinionFollow, InputBinding::Key(KeyCode::Z)),
+            (Action::MinionHold, InputBinding::Key(KeyCode::X)),
+            (Action::MinionAttack, InputBinding::Key(KeyCode::C)),
+            (Action::MinionGuard, InputBinding::Key(KeyCode::V)),
+        ]);
+        for (slot, key) in number_keys.into_iter().enumerate() {
+            keyboard.insert(Action::SelectSpell(slot), InputBinding::Key(key));
+        }
+
+        let gamepad = BTreeMap::from([
+            (Action::Cast, GamepadButtonType::RightTrigger2),
+            (Action::Blink, GamepadButtonType::South),
+            (Action::PreviousSpell, GamepadButtonType::LeftTrigger),
+            (Action::NextSpell, GamepadButtonType::RightTrigger),
+            (Action::MinionFollow, GamepadButtonType::DPadUp),
+            (Action::MinionHold, GamepadButtonType::DPadDown),
+            (Action::MinionAttack, GamepadButtonType::DPadRight),
+            (Action::MinionGuard, GamepadButtonType::DPadLeft),
+        ]);
+
+        Self { keyboard, gamepad }
+    }
+}
+
+/// Binds `action` to `binding`, handing the action's old binding to whichever other action
+/// was using `binding` before. Returns that other action, if there was one.
+fn rebind<T: Copy + PartialEq>(
+    bindings: &mut BTreeMap<Action, T>,
+    action: Action,
+    binding: T,
+) -> Option<Action> {
+    let conflict = bindings
+        .iter()
+        .find(|(other, bound)| **other != action && **bound == binding)
+        .map(|(other, _)| *other);
+    let previous = bindings.insert(action, binding);
+    if let Some(other) = conflict {
+        match previous {
+            Some(previous) => bindings.insert(other, previous),
+            None => bindings.remove(&other),
+        };
+    }
+    conflict
+}
+
+impl InputMap {
+    pub fn rebind_keyboard(&mut self, action: Action, binding: InputBinding) -> Option<Action> {
+        rebind(&mut self.keyboard, action, binding)
+    }
+
+    pub fn rebind_gamepad(&mut self, action: Action, button: GamepadButtonType) -> Option<Action> {
+        rebind(&mut self.gamepad, action, button)
+    }
+
+    /// The name of whatever the action is bound to on a device, or a dash if it isn't bound.
+    pub fn binding_name(&self, action: Action, device: InputDevice) -> String {
+        let name = match device {
+            InputDevice::KeyboardMouse => self.keyboard.get(&action).map(InputBinding::name),
+            InputDevice::Gamepad => self.gamepad.get(&action).copied().map(gamepad_button_name),
+        };
+        name.unwrap_or_else(|| "-".to_string())
+    }
+
+    /// Names a group of actions together, running single letters into each other like "WASD".
+    fn group_name(&self, actions: &[Action], device: InputDevice) -> String {
+        let names = actions
+            .iter()
+            .map(|action| self.binding_name(*action, device))
+            .collect::<Vec<_>>();
+        if names.iter().all(|name| name.chars().count() == 1) {
+            names.concat()
+        } else {
+            names.join("/")
+        }
+    }
+
+    pub fn controls_hint(&self, device: InputDevice) -> String {
+        let (movement, slots) = match device {
+            InputDevice::KeyboardMouse => (
+                format!(
+                    "{}: Move",
+                    self.group_name(
+                        &[
+                            Action::MoveUp,
+                            Action::MoveLeft,
+                            Action::MoveDown,
+                            Action::MoveRight,
+                        ],
+                        device,
+                    )
+                ),
+                format!(
+                    "/{}-{}",
+                    self.binding_name(Action::SelectSpell(0), device),
+                    self.binding_name(Action::SelectSpell(SPELL_SLOTS - 1), device)
+                ),
+            ),
+            InputDevice::Gamepad => ("LS: Move, RS: Aim".to_string(), String::new()),
+        };
+        format!(
+            "{}, {}: Attack (Hold to Charge), {}{}: Change Spells, {}: Blink, {}: Command Minions",
+            movement,
+            self.binding_name(Action::Cast, device),
+            self.group_name(&[Action::PreviousSpell, Action::NextSpell], device),
+            slots,
+            self.binding_name(Action::Blink, device),
+            self.group_name(&Action::MINION_COMMANDS, device),
+        )
+    }
+}
+
+/// The button the arrow keys or D-pad have landed on, if they've been used.
+#[derive(Default)]
+pub struct MenuFocus(pub Option<Entity>);
+
+/// What the controls screen is up to: waiting on a new binding, or reporting what just changed.
+#[derive(Default)]
+pub struct ControlsScreen {
+    pub listening: Option<(Action, InputDevice)>,
+    pub message: String,
+}
+
+/// Where in the world the lich is aiming, from either the cursor or the right stick.
+#[derive(Default)]
+pub struct PlayerAim(pub Option<Vec2>);
+
 #[derive(Component)]
 pub struct EnemyMorale {
     pub current: f32,
     pub change: f32,
     pub enemies_killed: u32,
+    pub minion_kills: u32,
+}
+
+pub struct MinionOrders {
+    pub command: MinionCommand,
+}
+
+impl Default for MinionOrders {
+    fn default() -> Self {
+        Self {
+            command: MinionCommand::Follow,
+        }
+    }
 }
 
 #[derive(Component)]
@@ -273,6 +1703,7 @@ pub struct WaveManager {
 pub struct CurrentDay {
     pub day: u32,
     pub player_damaged: f32,
+    pub deaths: u32,
 }
 
 #[derive(Component)]
@@ -284,21 +1715,26 @@ impl CurrentTime {
     }
 }
 
-#[derive(PhysicsLayer)]
+#[derive(PhysicsLayer, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum GamePhysicsLayer {
     Player,
     PlayerAttack,
     Enemy,
     EnemyAttack,
+    Minion,
+    Phylactery,
 }
 
 #[derive(SystemLabel, Debug, Hash, PartialEq, Eq, Clone)]
 pub enum Label {
+    Input,
     Movement,
+    CollisionRouting,
     CollisionCheck,
     HealthUpdate,
     Despawn,
     UpdateSprites,
+    Camera,
 }
 
 // Functions
@@ -360,6 +1796,16 @@ pub fn get_cursor_position(
     }
 }
 
+/// Gets the area of the world currently visible to a camera, as its bottom left and top right corners.
+pub fn camera_view_bounds(
+    transform: &Transform,
+    projection: &OrthographicProjection,
+) -> (Vec2, Vec2) {
+    let half_size = Vec2::new(SCREEN_WIDTH, SCREEN_HEIGHT) * projection.scale / 2.0;
+    let center = transform.translation.truncate();
+    (center - half_size, center + half_size)
+}
+
 // Systems
 
 /// Ticks all entities that can despawn, and despawn them if their time is up
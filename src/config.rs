@@ -0,0 +1,44 @@
+use bevy::log::{error, warn};
+use ron::ser::PrettyConfig;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fs, io, path::PathBuf};
+
+const CONFIG_DIR: &str = "config";
+
+fn config_path(name: &str) -> PathBuf {
+    PathBuf::from(CONFIG_DIR).join(format!("{}.ron", name))
+}
+
+/// Reads `config/<name>.ron`, writing out the defaults first if the file doesn't exist yet.
+/// A file that can't be parsed is left alone, so hand edits aren't lost to a typo.
+pub fn load_config<T: Serialize + DeserializeOwned + Default>(name: &str) -> T {
+    match fs::read_to_string(config_path(name)) {
+        Ok(contents) => ron::from_str(&contents).unwrap_or_else(|err| {
+            warn!("Couldn't read {} config, using defaults: {}", name, err);
+            T::default()
+        }),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let config = T::default();
+            save_config(name, &config);
+            config
+        }
+        Err(err) => {
+            warn!("Couldn't open {} config, using defaults: {}", name, err);
+            T::default()
+        }
+    }
+}
+
+/// Writes `config/<name>.ron`. Failing to save isn't worth stopping the game over.
+pub fn save_config<T: Serialize>(name: &str, config: &T) {
+    let result = ron::ser::to_string_pretty(config, PrettyConfig::new())
+        .map_err(|err| err.to_string())
+        .and_then(|contents| {
+            fs::create_dir_all(CONFIG_DIR)
+                .and_then(|_| fs::write(config_path(name), contents))
+                .map_err(|err| err.to_string())
+        });
+    if let Err(err) = result {
+        error!("Couldn't save {} config: {}", name, err);
+    }
+}
@@ -1,7 +1,13 @@
-use crate::common::{
-    DamagePlayerEvent, DamagesPlayer, DespawnTimer, Enemy, EnemyAI, EnemyMorale, EnemyProjectile,
-    EnemyShoots, GamePhysicsLayer, GameSprites, Health, Player, Vec3Utils, WaveCore, WaveManager,
-    SCREEN_HEIGHT, SCREEN_WIDTH,
+use crate::{
+    arena::{ArenaLayout, EnemyPaths, FlowField},
+    common::{
+        camera_view_bounds, Corpse, DamageEnemyEvent, DamagePlayerEvent, DamageSource, DamageType,
+        DamagesPlayer, DespawnTimer, Enemy, EnemyAI, EnemyAttackHitEvent, EnemyContactEvent,
+        EnemyMorale, EnemyProjectile, EnemyShoots, EnemyVariant, GamePhysicsLayer, GameSprites,
+        Health, HitTarget, KilledByMinion, MainCamera, MinionKind, Phylactery, Player, Resistances,
+        RunState, StatusEffectKind, StatusEffects, Vec3Utils, WaveCore, WaveKind, WaveManager,
+        SCREEN_HEIGHT, SCREEN_WIDTH, STATUS_EFFECT_TICK, WORLD_HEIGHT,
+    },
 };
 use bevy::prelude::*;
 use heron::prelude::*;
@@ -13,24 +19,55 @@ pub fn spawn_enemy_wave(
     mut wave_manager: ResMut<WaveManager>,
     sprites: Res<GameSprites>,
     time: Res<Time>,
+    arena: Res<ArenaLayout>,
+    q_camera: Query<(&Transform, &OrthographicProjection), With<MainCamera>>,
 ) {
     wave_manager.wave_timer.tick(time.delta());
     if wave_manager.wave_timer.finished() && wave_manager.active_waves < wave_manager.max_waves {
-        let wave_to_spawn = alea::u32_less_than(3);
+        let wave_to_spawn = alea::u32_less_than(4);
+        // Waves always march in from just out of sight, below the camera
+        let view = match q_camera.iter().next() {
+            Some((transform, projection)) => camera_view_bounds(transform, projection),
+            None => (
+                Vec2::new(-SCREEN_WIDTH, -SCREEN_HEIGHT) / 2.0,
+                Vec2::new(SCREEN_WIDTH, SCREEN_HEIGHT) / 2.0,
+            ),
+        };
+        let road_x = arena
+            .tile_to_world(arena.road_entry.0, arena.road_entry.1)
+            .x
+            .clamp(view.0.x, view.1.x);
         match wave_to_spawn {
-            x if x < 1 => spawn_knight_line_wave(&mut commands, sprites),
-            x if x < 2 => spawn_knight_square_wave(&mut commands, sprites),
-            _ => spawn_archer_square_wave(&mut commands, sprites),
+            x if x < 1 => spawn_knight_line_wave(&mut commands, sprites, view),
+            x if x < 2 => spawn_knight_square_wave(&mut commands, sprites, view, road_x, false),
+            x if x < 3 => spawn_knight_square_wave(&mut commands, sprites, view, road_x, true),
+            _ => spawn_archer_square_wave(&mut commands, sprites, view, road_x),
         }
         wave_manager.active_waves += 1;
         wave_manager.wave_timer.reset();
     }
 }
 
-pub fn spawn_knight(commands: &mut Commands, sprite: Handle<Image>, position: Vec3, core: Entity) {
+pub fn spawn_knight(
+    commands: &mut Commands,
+    sprite: Handle<Image>,
+    position: Vec3,
+    core: Entity,
+    variant: EnemyVariant,
+    raids_phylactery: bool,
+) {
+    let (speed, health, fear_threshold) = match variant {
+        EnemyVariant::Regular => (120.0, 3.0, 2.5),
+        EnemyVariant::Armored => (90.0, 4.0, 2.5),
+        EnemyVariant::Priest => (120.0, 3.0, 1.0),
+    };
     commands
         .spawn_bundle(SpriteBundle {
             texture: sprite,
+            sprite: Sprite {
+                color: variant.color(),
+                ..Default::default()
+            },
             transform: Transform {
                 translation: position,
                 scale: Vec3::new(1.5, 1.5, 0.0),
@@ -39,24 +76,71 @@ pub fn spawn_knight(commands: &mut Commands, sprite: Handle<Image>, position: Ve
             ..Default::default()
         })
         .insert(Enemy {
-            ai: EnemyAI::ChasesPlayer { speed: 120.0 },
+            ai: if raids_phylactery {
+                EnemyAI::RaidsPhylactery { speed }
+            } else {
+                EnemyAI::ChasesPlayer { speed }
+            },
+            variant,
             wave_core: Some(core),
-            fear_threshold: 2.5,
+            fear_threshold,
+            calm_ai: None,
         })
+        .insert(StatusEffects::default())
+        .insert(variant.resistances())
         .insert(RigidBody::KinematicVelocityBased)
         .insert(CollisionShape::Sphere { radius: 10.0 })
         .insert(Velocity::from_linear(Vec3::ZERO))
         .insert(
             CollisionLayers::none()
                 .with_group(GamePhysicsLayer::Enemy)
-                .with_masks(&[GamePhysicsLayer::PlayerAttack, GamePhysicsLayer::Player]),
+                .with_masks(&[
+                    GamePhysicsLayer::PlayerAttack,
+                    GamePhysicsLayer::Player,
+                    GamePhysicsLayer::Minion,
+                    GamePhysicsLayer::Phylactery,
+                ]),
         )
         .insert(DamagesPlayer {
             damage: 1.0,
+            damage_type: DamageType::Physical,
             tick: Timer::from_seconds(1.5, true),
             is_damaging: false,
         })
-        .insert(Health::full(3.0));
+        .insert(Health::full(health));
+}
+
+/// Picks which kind of knight to spawn, with tougher variants showing up now and then.
+fn random_knight_variant() -> EnemyVariant {
+    match alea::u32_less_than(20) {
+        0..=2 => EnemyVariant::Armored,
+        3 => EnemyVariant::Priest,
+        _ => EnemyVariant::Regular,
+    }
+}
+
+pub fn spawn_corpse(
+    commands: &mut Commands,
+    sprite: Handle<Image>,
+    position: Vec3,
+    kind: MinionKind,
+) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: sprite,
+            sprite: Sprite {
+                color: Color::rgb(0.4, 0.4, 0.4),
+                ..Default::default()
+            },
+            transform: Transform {
+                translation: Vec3::new(position.x, position.y, 0.05),
+                scale: Vec3::new(1.5, 1.5, 0.0),
+                rotation: Quat::from_rotation_z(PI / 2.0),
+            },
+            ..Default::default()
+        })
+        .insert(Corpse(kind))
+        .insert(DespawnTimer(Timer::from_seconds(30.0, false)));
 }
 
 pub fn spawn_archer(
@@ -80,74 +164,123 @@ pub fn spawn_archer(
             ai: EnemyAI::Archer {
                 target_y: position.y + y_offset,
             },
+            variant: EnemyVariant::Regular,
             wave_core: Some(core),
             fear_threshold: 1.5,
+            calm_ai: None,
         })
+        .insert(StatusEffects::default())
+        .insert(Resistances::default())
         .insert(RigidBody::KinematicVelocityBased)
         .insert(CollisionShape::Sphere { radius: 10.0 })
         .insert(Velocity::from_linear(
             Vec3::Y.rotate_2d(alea::f32_in_range(-PI / 128.0, PI / 128.0)) * 180.0,
         ))
-        .insert(CollisionLayers::new(
-            GamePhysicsLayer::Enemy,
-            GamePhysicsLayer::PlayerAttack,
-        ))
+        .insert(
+            CollisionLayers::none()
+                .with_group(GamePhysicsLayer::Enemy)
+                .with_masks(&[GamePhysicsLayer::PlayerAttack, GamePhysicsLayer::Minion]),
+        )
         .insert(Health::full(3.0))
         .insert(EnemyShoots(Timer::from_seconds(2.0, true)));
 }
 
-pub fn spawn_knight_square_wave(commands: &mut Commands, sprites: Res<GameSprites>) {
+/// Spawns a block of knights below the given view, marching in around the road at `road_x`.
+/// Raiders make straight for the phylactery instead of the lich.
+pub fn spawn_knight_square_wave(
+    commands: &mut Commands,
+    sprites: Res<GameSprites>,
+    (view_min, view_max): (Vec2, Vec2),
+    road_x: f32,
+    raiders: bool,
+) {
     let wave_width = alea::u32_in_range(4, 7);
     let wave_height = alea::u32_in_range(3, 5);
-    let start_x = alea::f32_in_range(-SCREEN_WIDTH / 2.0, SCREEN_WIDTH / 2.0);
+    let start_x = (road_x + alea::f32_in_range(-SCREEN_WIDTH / 4.0, SCREEN_WIDTH / 4.0))
+        .clamp(view_min.x, view_max.x);
 
     let wave_core = commands
         .spawn()
         .insert(WaveCore {
             remaining: wave_width * wave_height,
+            kind: if raiders {
+                WaveKind::Raiders
+            } else {
+                WaveKind::Knights
+            },
         })
         .id();
 
     for (x, y) in (0..wave_width).cartesian_product(0..wave_height) {
         let spawn_x = start_x + ((x as f32 - x as f32 / 2.0) * 40.0);
-        let spawn_y = (-SCREEN_HEIGHT * 0.6) - ((y as f32 / 2.0) * 60.0);
+        let spawn_y = (view_min.y - SCREEN_HEIGHT * 0.1) - ((y as f32 / 2.0) * 60.0);
         let pos = Vec3::new(spawn_x, spawn_y, 0.1);
-        spawn_knight(commands, sprites.soldier.clone(), pos, wave_core);
+        spawn_knight(
+            commands,
+            sprites.soldier.clone(),
+            pos,
+            wave_core,
+            random_knight_variant(),
+            raiders,
+        );
     }
 }
 
-pub fn spawn_knight_line_wave(commands: &mut Commands, sprites: Res<GameSprites>) {
+/// Spawns a line of knights spanning the whole width of the given view, below it.
+pub fn spawn_knight_line_wave(
+    commands: &mut Commands,
+    sprites: Res<GameSprites>,
+    (view_min, view_max): (Vec2, Vec2),
+) {
     let wave_size = alea::u32_in_range(20, 25);
+    let view_width = view_max.x - view_min.x;
 
     let wave_core = commands
         .spawn()
         .insert(WaveCore {
             remaining: wave_size,
+            kind: WaveKind::Knights,
         })
         .id();
 
     for i in 0..wave_size {
-        let spawn_x = (i as f32 * (SCREEN_WIDTH / wave_size as f32)) - SCREEN_WIDTH / 2.0;
-        let pos = Vec3::new(spawn_x, -SCREEN_HEIGHT * 0.6, 0.1);
-        spawn_knight(commands, sprites.soldier.clone(), pos, wave_core);
+        let spawn_x = (i as f32 * (view_width / wave_size as f32)) + view_min.x;
+        let pos = Vec3::new(spawn_x, view_min.y - SCREEN_HEIGHT * 0.1, 0.1);
+        spawn_knight(
+            commands,
+            sprites.soldier.clone(),
+            pos,
+            wave_core,
+            random_knight_variant(),
+            false,
+        );
     }
 }
 
-pub fn spawn_archer_square_wave(commands: &mut Commands, sprites: Res<GameSprites>) {
+/// Spawns a block of archers below the given view, marching in around the road at `road_x`.
+pub fn spawn_archer_square_wave(
+    commands: &mut Commands,
+    sprites: Res<GameSprites>,
+    (view_min, view_max): (Vec2, Vec2),
+    road_x: f32,
+) {
     let wave_width = alea::u32_in_range(3, 4);
     let wave_height = alea::u32_in_range(2, 3);
-    let start_x = alea::f32_in_range(-SCREEN_WIDTH * 0.3, SCREEN_WIDTH * 0.3);
+    let view_width = view_max.x - view_min.x;
+    let start_x = (road_x + alea::f32_in_range(-SCREEN_WIDTH * 0.15, SCREEN_WIDTH * 0.15))
+        .clamp(view_min.x + view_width * 0.2, view_max.x - view_width * 0.2);
 
     let wave_core = commands
         .spawn()
         .insert(WaveCore {
             remaining: wave_width * wave_height,
+            kind: WaveKind::Archers,
         })
         .id();
 
     for (x, y) in (0..wave_width).cartesian_product(0..wave_height) {
         let spawn_x = start_x + ((x as f32 - x as f32 / 2.0) * 40.0);
-        let spawn_y = (-SCREEN_HEIGHT * 0.6) - ((y as f32 / 2.0) * 60.0);
+        let spawn_y = (view_min.y - SCREEN_HEIGHT * 0.1) - ((y as f32 / 2.0) * 60.0);
         let pos = Vec3::new(spawn_x, spawn_y, 0.1);
         spawn_archer(
             commands,
@@ -159,24 +292,74 @@ pub fn spawn_archer_square_wave(commands: &mut Commands, sprites: Res<GameSprite
     }
 }
 
+/// Points the enemies' path towards the lich at wherever it stands now.
+pub fn update_enemy_paths(
+    arena: Res<ArenaLayout>,
+    mut paths: ResMut<EnemyPaths>,
+    q_player: Query<&Transform, With<Player>>,
+) {
+    if let Some(player) = q_player.iter().next() {
+        let tile = arena.nearest_tile(player.translation.truncate());
+        if paths.to_player.goals() != [tile] {
+            paths.to_player = FlowField::towards(&arena, &[tile]);
+        }
+    }
+}
+
+/// Where to steer for to reach a target along a flow field, around any obstacles in the way.
+fn waypoint(arena: &ArenaLayout, field: &FlowField, from: Vec3, target: Vec3) -> Vec3 {
+    match field.next_tile(arena, from.truncate()) {
+        Some((x, y)) => arena.tile_to_world(x, y).extend(target.z),
+        None => target,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn update_enemy(
-    mut q_enemies: Query<(Entity, &mut Enemy, &Transform, &mut Velocity), Without<Player>>,
+    mut q_enemies: Query<
+        (
+            Entity,
+            &mut Enemy,
+            &Transform,
+            &mut Velocity,
+            &StatusEffects,
+        ),
+        Without<Player>,
+    >,
     q_enemies_other: Query<(Entity, &Transform), With<Enemy>>,
     q_player: Query<&Transform, With<Player>>,
+    q_phylactery: Query<&Transform, (With<Phylactery>, Without<Enemy>)>,
+    arena: Res<ArenaLayout>,
+    paths: Res<EnemyPaths>,
+    time: Res<Time>,
 ) {
     if let Some(player) = q_player.iter().next() {
-        for (ent, enemy, transform, mut velocity) in q_enemies.iter_mut() {
+        for (ent, enemy, transform, mut velocity, effects) in q_enemies.iter_mut() {
             let current_pos = transform.translation;
+            if effects.is_held() {
+                velocity.linear = Vec3::ZERO;
+                continue;
+            }
             match enemy.ai {
-                EnemyAI::ChasesPlayer { speed } => {
-                    // Seeking and arrival
-                    let desired_velocity =
-                        (player.translation.truncate() - current_pos.truncate()).extend(0.0);
-                    let distance = desired_velocity.length();
-                    let desired_velocity = if distance < 32.0 {
+                EnemyAI::ChasesPlayer { speed } | EnemyAI::RaidsPhylactery { speed } => {
+                    let speed = speed * effects.speed_factor();
+                    // Raiders ignore the lich and go straight for the phylactery, pressing right up against it
+                    let (target, arrival, field) = match (enemy.ai, q_phylactery.iter().next()) {
+                        (EnemyAI::RaidsPhylactery { speed: _ }, Some(phylactery)) => {
+                            (phylactery.translation, 16.0, &paths.to_phylactery)
+                        }
+                        _ => (player.translation, 32.0, &paths.to_player),
+                    };
+                    // Seeking and arrival, marching along the arena's paths
+                    let distance = target.truncate().distance(current_pos.truncate());
+                    let desired_velocity = if distance < arrival {
                         Vec3::ZERO
                     } else {
-                        desired_velocity.normalize() * speed
+                        let towards = waypoint(&arena, field, current_pos, target);
+                        (towards.truncate() - current_pos.truncate())
+                            .extend(0.0)
+                            .normalize_or_zero()
+                            * speed
                     };
                     let seek_force = desired_velocity - velocity.linear;
 
@@ -215,7 +398,16 @@ pub fn update_enemy(
                     velocity.linear = (velocity.linear + steering).clamp_length_max(speed);
                 }
                 EnemyAI::Archer { target_y } => {
-                    if velocity.linear != Vec3::ZERO && transform.translation.y >= target_y {
+                    if transform.translation.y < target_y {
+                        // Keep marching towards the firing line, even after being held or scared
+                        velocity.linear = velocity
+                            .linear
+                            .try_normalize()
+                            .filter(|v| v.y > 0.0)
+                            .unwrap_or(Vec3::Y)
+                            * 180.0
+                            * effects.speed_factor();
+                    } else if velocity.linear != Vec3::ZERO {
                         let sub = velocity.linear.normalize() * 15.0;
                         velocity.linear -= sub;
                         if velocity.linear.y <= 0.0 {
@@ -224,8 +416,17 @@ pub fn update_enemy(
                     }
                 }
                 EnemyAI::Afraid { speed } => {
-                    let desired_velocity = (Vec3::Y * ((-SCREEN_HEIGHT * 0.7) - current_pos.y))
-                        .normalize()
+                    let speed = speed * effects.speed_factor();
+                    let retreat = Vec3::new(
+                        current_pos.x,
+                        -WORLD_HEIGHT / 2.0 - SCREEN_HEIGHT * 0.2,
+                        current_pos.z,
+                    );
+                    let desired_velocity = (waypoint(&arena, &paths.retreat, current_pos, retreat)
+                        - current_pos)
+                        .truncate()
+                        .extend(0.0)
+                        .normalize_or_zero()
                         * speed
                         * 3.0;
                     let seek_force = desired_velocity - velocity.linear;
@@ -240,20 +441,35 @@ pub fn update_enemy(
                     velocity.linear = (velocity.linear + steering).clamp_length_max(speed);
                 }
             }
+
+            // Like the lich, enemies slide along obstacles rather than walking through them
+            let step = velocity.linear.truncate() * time.delta_seconds();
+            let current = current_pos.truncate();
+            if !arena.is_walkable_at(current + Vec2::new(step.x, 0.0)) {
+                velocity.linear.x = 0.0;
+            }
+            if !arena.is_walkable_at(
+                current + Vec2::new(velocity.linear.x * time.delta_seconds(), step.y),
+            ) {
+                velocity.linear.y = 0.0;
+            }
         }
     }
 }
 
 pub fn update_enemy_shoot(
     mut commands: Commands,
-    mut q_shoots: Query<(&mut EnemyShoots, &Velocity, &Transform), With<Enemy>>,
+    mut q_shoots: Query<(&mut EnemyShoots, &Velocity, &Transform, &StatusEffects), With<Enemy>>,
     q_player: Query<&Transform, With<Player>>,
     sprites: Res<GameSprites>,
     time: Res<Time>,
 ) {
     if let Some(p_transform) = q_player.iter().next() {
-        for (mut timer, vel, e_transform) in q_shoots.iter_mut() {
-            if vel.linear == Vec3::ZERO && timer.0.tick(time.delta()).just_finished() {
+        for (mut timer, vel, e_transform, effects) in q_shoots.iter_mut() {
+            if vel.linear == Vec3::ZERO
+                && !effects.has(StatusEffectKind::Stunned)
+                && timer.0.tick(time.delta()).just_finished()
+            {
                 commands
                     .spawn_bundle(SpriteBundle {
                         texture: sprites.arrow.clone(),
@@ -273,17 +489,21 @@ pub fn update_enemy_shoot(
                         half_extends: Vec3::new(21.0, 7.0, 0.0),
                         border_radius: None,
                     })
-                    .insert(CollisionLayers::new(
-                        GamePhysicsLayer::EnemyAttack,
-                        GamePhysicsLayer::Player,
-                    ))
+                    .insert(
+                        CollisionLayers::none()
+                            .with_group(GamePhysicsLayer::EnemyAttack)
+                            .with_masks(&[GamePhysicsLayer::Player, GamePhysicsLayer::Minion]),
+                    )
                     .insert(Velocity::from_linear(
                         (p_transform.translation.truncate() - e_transform.translation.truncate())
                             .extend(0.0)
                             .normalize()
                             * 400.0,
                     ))
-                    .insert(EnemyProjectile)
+                    .insert(EnemyProjectile {
+                        damage: 1.0,
+                        damage_type: DamageType::Physical,
+                    })
                     .insert(DespawnTimer(Timer::from_seconds(3.0, false)));
             }
         }
@@ -291,11 +511,13 @@ pub fn update_enemy_shoot(
 }
 
 pub fn update_enemy_render(
-    mut q_enemies: Query<(&Enemy, &Transform, &Health, &mut Sprite)>,
+    mut q_enemies: Query<(&Enemy, &Transform, &Health, &StatusEffects, &mut Sprite)>,
     q_player: Query<&Transform, With<Player>>,
+    q_phylactery: Query<&Transform, With<Phylactery>>,
 ) {
     if let Some(player) = q_player.iter().next() {
-        for (enemy, transform, health, mut sprite) in q_enemies.iter_mut() {
+        let phylactery = q_phylactery.iter().next().unwrap_or(player);
+        for (enemy, transform, health, effects, mut sprite) in q_enemies.iter_mut() {
             match enemy.ai {
                 EnemyAI::ChasesPlayer { speed: _ } => {
                     if transform.translation.x > player.translation.x {
@@ -304,6 +526,13 @@ pub fn update_enemy_render(
                         sprite.flip_x = false;
                     }
                 }
+                EnemyAI::RaidsPhylactery { speed: _ } => {
+                    if transform.translation.x > phylactery.translation.x {
+                        sprite.flip_x = true;
+                    } else {
+                        sprite.flip_x = false;
+                    }
+                }
                 EnemyAI::Afraid { speed: _ } => {
                     if transform.translation.x > player.translation.x {
                         sprite.flip_x = false;
@@ -319,80 +548,122 @@ pub fn update_enemy_render(
                     0.25 + (health.current.max(0.0) / health.maximum) / 2.0,
                     0.25 + (health.current.max(0.0) / health.maximum) / 2.0,
                 );
+            } else if let Some(effect) = effects.0.first() {
+                sprite.color = effect.kind.tint();
             } else if let EnemyAI::Afraid { speed: _ } = enemy.ai {
                 sprite.color = Color::rgb(1.0, 0.5, 1.0);
+            } else {
+                sprite.color = enemy.variant.color();
             }
         }
     }
 }
 
-pub fn check_enemy_player_collision(
-    mut collision_events: EventReader<CollisionEvent>,
-    mut q_enemies: Query<&mut DamagesPlayer>,
-    mut damage_writer: EventWriter<DamagePlayerEvent>,
+/// Resolves every hit on an enemy, taking its resistances into account.
+pub fn apply_enemy_damage(
+    mut commands: Commands,
+    mut damage_reader: EventReader<DamageEnemyEvent>,
+    mut q_enemies: Query<(&mut Health, &mut Enemy, &mut StatusEffects, &Resistances)>,
+    run_state: Res<RunState>,
 ) {
-    fn is_player(layers: CollisionLayers) -> bool {
-        layers.contains_group(GamePhysicsLayer::Player)
-            && !layers.contains_group(GamePhysicsLayer::Enemy)
-    }
-    fn is_enemy(layers: CollisionLayers) -> bool {
-        layers.contains_group(GamePhysicsLayer::Enemy)
-            && !layers.contains_group(GamePhysicsLayer::Player)
+    for hit in damage_reader.iter() {
+        if let Ok((mut health, mut enemy, mut effects, resistances)) = q_enemies.get_mut(hit.enemy)
+        {
+            let was_alive = health.current > 0.0;
+            // Neither minions nor burns benefit from the lich's own spell upgrades
+            let damage = if hit.source == DamageSource::Spell {
+                hit.damage * run_state.upgrades.damage_multiplier()
+            } else {
+                hit.damage
+            };
+            health.current -= resistances.damage_taken(damage, hit.damage_type);
+            if let Some((kind, seconds)) = hit.effect.filter(|(k, _)| !resistances.resists(*k)) {
+                effects.apply(kind, seconds);
+                if kind == StatusEffectKind::Terrified {
+                    enemy.frighten(true);
+                }
+            }
+            if health.current <= enemy.fear_threshold {
+                enemy.frighten(false);
+            }
+            if hit.source == DamageSource::Minion && was_alive && health.current <= 0.0 {
+                commands.entity(hit.enemy).insert(KilledByMinion);
+            }
+        }
     }
+}
 
-    for (evt, e_enemy) in collision_events.iter().filter_map(|event| {
-        let (entity_1, entity_2) = event.rigid_body_entities();
-        let (layers_1, layers_2) = event.collision_layers();
-        if is_enemy(layers_1) && is_player(layers_2) {
-            Some((event, entity_1))
-        } else if is_enemy(layers_2) && is_player(layers_1) {
-            Some((event, entity_2))
-        } else {
-            None
+/// How much damage each stack of burning deals every second.
+const BURN_DAMAGE_PER_SECOND: f32 = 0.5;
+
+/// Ticks down every status effect, burning enemies and calming them down as effects wear off.
+pub fn update_status_effects(
+    mut q_enemies: Query<(Entity, &mut StatusEffects, &mut Enemy)>,
+    mut damage_writer: EventWriter<DamageEnemyEvent>,
+    time: Res<Time>,
+) {
+    for (ent, mut effects, mut enemy) in q_enemies.iter_mut() {
+        for effect in effects.0.iter_mut() {
+            effect.duration.tick(time.delta());
+            let ticks = effect.tick.tick(time.delta()).times_finished();
+            if effect.kind == StatusEffectKind::Burning && ticks > 0 {
+                damage_writer.send(DamageEnemyEvent {
+                    enemy: ent,
+                    damage: BURN_DAMAGE_PER_SECOND
+                        * STATUS_EFFECT_TICK
+                        * (effect.stacks * ticks) as f32,
+                    damage_type: DamageType::Fire,
+                    effect: None,
+                    source: DamageSource::StatusEffect,
+                });
+            }
         }
-    }) {
-        if let Ok(mut enemy) = q_enemies.get_mut(e_enemy) {
-            match evt {
-                CollisionEvent::Started(_d1, _d2) => {
-                    damage_writer.send(DamagePlayerEvent(enemy.damage));
-                    enemy.is_damaging = true;
-                }
-                CollisionEvent::Stopped(_d1, _d2) => {
-                    enemy.is_damaging = false;
-                }
+        let terror_ended = effects
+            .0
+            .iter()
+            .any(|e| e.kind == StatusEffectKind::Terrified && e.duration.finished());
+        effects.0.retain(|e| !e.duration.finished());
+        if terror_ended {
+            if let Some(calm_ai) = enemy.calm_ai.take() {
+                enemy.ai = calm_ai;
             }
         }
     }
 }
 
-pub fn enemy_projectile_damage_player(
-    mut commands: Commands,
-    mut collision_events: EventReader<CollisionEvent>,
+pub fn check_enemy_player_collision(
+    mut contact_events: EventReader<EnemyContactEvent>,
+    mut q_enemies: Query<&mut DamagesPlayer>,
     mut damage_writer: EventWriter<DamagePlayerEvent>,
 ) {
-    fn is_player(layers: CollisionLayers) -> bool {
-        layers.contains_group(GamePhysicsLayer::Player)
-            && !layers.contains_group(GamePhysicsLayer::EnemyAttack)
-    }
-    fn is_projectile(layers: CollisionLayers) -> bool {
-        layers.contains_group(GamePhysicsLayer::EnemyAttack)
-            && !layers.contains_group(GamePhysicsLayer::Player)
+    for contact in contact_events
+        .iter()
+        .filter(|c| c.target_kind == HitTarget::Player)
+    {
+        if let Ok(mut enemy) = q_enemies.get_mut(contact.enemy) {
+            if contact.started {
+                damage_writer.send(DamagePlayerEvent(enemy.damage));
+                enemy.is_damaging = true;
+            } else {
+                enemy.is_damaging = false;
+            }
+        }
     }
+}
 
-    for (evt, ent) in collision_events.iter().filter_map(|event| {
-        let (entity_1, entity_2) = event.rigid_body_entities();
-        let (layers_1, layers_2) = event.collision_layers();
-        if is_projectile(layers_1) && is_player(layers_2) {
-            Some((event, entity_1))
-        } else if is_projectile(layers_2) && is_player(layers_1) {
-            Some((event, entity_2))
-        } else {
-            None
-        }
-    }) {
-        if let CollisionEvent::Started(_, _) = evt {
-            damage_writer.send(DamagePlayerEvent(1.0));
-            commands.entity(ent).despawn();
+pub fn enemy_projectile_damage_player(
+    mut commands: Commands,
+    mut hit_events: EventReader<EnemyAttackHitEvent>,
+    q_projectiles: Query<&EnemyProjectile>,
+    mut damage_writer: EventWriter<DamagePlayerEvent>,
+) {
+    for hit in hit_events
+        .iter()
+        .filter(|h| h.target_kind == HitTarget::Player)
+    {
+        if let Ok(projectile) = q_projectiles.get(hit.attack) {
+            damage_writer.send(DamagePlayerEvent(projectile.damage));
+            commands.entity(hit.attack).despawn();
         }
     }
 }
@@ -409,21 +680,56 @@ pub fn enemy_damage_player(
     }
 }
 
+#[allow(clippy::type_complexity)]
 pub fn despawn_enemies(
     mut commands: Commands,
-    q_enemies: Query<(Entity, &Health, &Transform, &Enemy)>,
+    q_enemies: Query<(
+        Entity,
+        &Health,
+        &Transform,
+        &Enemy,
+        &Handle<Image>,
+        Option<&KilledByMinion>,
+        Option<&EnemyShoots>,
+    )>,
     mut q_wave_cores: Query<(Entity, &mut WaveCore)>,
+    q_phylactery: Query<&Transform, With<Phylactery>>,
     mut morale: ResMut<EnemyMorale>,
     mut wave_manager: ResMut<WaveManager>,
 ) {
-    for (ent, health, transform, enemy) in q_enemies.iter() {
+    for (ent, health, transform, enemy, texture, killed_by_minion, shoots) in q_enemies.iter() {
         let despawned = if health.current <= 0.0 {
             commands.entity(ent).despawn();
+            let corpse_kind = if shoots.is_some() {
+                MinionKind::Ghoul
+            } else {
+                MinionKind::Skeleton
+            };
+            spawn_corpse(
+                &mut commands,
+                texture.clone(),
+                transform.translation,
+                corpse_kind,
+            );
             morale.enemies_killed += 1;
-            morale.change -= 0.05;
+            if killed_by_minion.is_some() {
+                // Watching fallen comrades rise against them is far more demoralizing
+                morale.minion_kills += 1;
+                morale.change -= 0.1;
+            } else {
+                morale.change -= 0.05;
+            }
+            if let (EnemyAI::RaidsPhylactery { speed: _ }, Some(phylactery)) =
+                (enemy.ai, q_phylactery.iter().next())
+            {
+                // Raids broken right at the phylactery's foot shake the whole army
+                if transform.translation.distance(phylactery.translation) <= 160.0 {
+                    morale.change -= 0.05;
+                }
+            }
             true
         } else if let EnemyAI::Afraid { speed: _ } = enemy.ai {
-            if transform.translation.y <= -SCREEN_HEIGHT * 0.6 {
+            if transform.translation.y <= -WORLD_HEIGHT / 2.0 - SCREEN_HEIGHT * 0.1 {
                 if health.current > enemy.fear_threshold {
                     morale.change += 0.05;
                 } else {
@@ -0,0 +1,592 @@
+use crate::{
+    common::{
+        camera_view_bounds, Action, ColorPalette, Enemy, EnemyAI, GameFonts, GameSprites, InGameUI,
+        InputDevice, InputMap, LightningStrikeBolt, MainCamera, Mana, MinimapMarker,
+        MinimapMarkerKind, OffscreenIndicator, Player, PlayerAim, PlayerSpell, PlayerSpellData,
+        Reforming, ReticlePart, ReticleTexture, RunState, SpellBarElement, Ui, Vec3Utils, WaveCore,
+        WaveKind, WORLD_HEIGHT, WORLD_WIDTH,
+    },
+    player::{
+        CHAIN_LIGHTNING_RADIUS, FEAR_WAVE_WIDTH, FIREBALL_SPREAD, LIGHTNING_STORM_RADIUS,
+        MAX_CHARGE, RAISE_DEAD_RADIUS,
+    },
+};
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    utils::HashMap,
+};
+use heron::prelude::*;
+
+const INDICATOR_MARGIN: f32 = 32.0;
+
+const MINIMAP_WIDTH: f32 = 160.0;
+const MINIMAP_HEIGHT: f32 = MINIMAP_WIDTH * WORLD_HEIGHT / WORLD_WIDTH;
+
+const SPELL_SLOT_SIZE: f32 = 56.0;
+const SPELL_ICON_SIZE: f32 = 36.0;
+const SPELL_SWEEP_DOTS: usize = 12;
+const SPELL_SWEEP_RADIUS: f32 = 24.0;
+const SPELL_SLOT_SELECTED: Color = Color::rgb(0.9, 0.75, 0.3);
+const SPELL_SLOT_NORMAL: Color = Color::rgba(0.0, 0.0, 0.0, 0.6);
+
+const RETICLE_TEXTURE_SIZE: u32 = 256;
+const RETICLE_THICKNESS: f32 = 4.0;
+const RETICLE_COOLDOWN: Color = Color::rgba(1.0, 0.2, 0.2, 0.6);
+/// How far the fireball paths are drawn out from the lich, at most.
+const RETICLE_SPREAD_LENGTH: f32 = 240.0;
+
+fn wave_color(kind: WaveKind, palette: &ColorPalette) -> Color {
+    match kind {
+        WaveKind::Knights => palette.knights,
+        WaveKind::Archers => palette.archers,
+        WaveKind::Raiders => palette.raiders,
+    }
+}
+
+/// Points arrows at the edge of the screen towards every wave that can't currently be seen.
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+pub fn update_offscreen_indicators(
+    mut commands: Commands,
+    q_wave_cores: Query<(Entity, &WaveCore)>,
+    q_enemies: Query<(&Enemy, &Transform)>,
+    q_camera: Query<(&Transform, &OrthographicProjection), With<MainCamera>>,
+    mut q_indicators: Query<
+        (
+            Entity,
+            &OffscreenIndicator,
+            &mut Transform,
+            &mut Visibility,
+            Option<&mut Sprite>,
+            Option<&mut Text>,
+        ),
+        (Without<Enemy>, Without<MainCamera>),
+    >,
+    sprites: Res<GameSprites>,
+    fonts: Res<GameFonts>,
+    palette: Res<ColorPalette>,
+) {
+    let (view_min, view_max) = match q_camera.iter().next() {
+        Some((transform, projection)) => camera_view_bounds(transform, projection),
+        None => return,
+    };
+
+    let mut wave_positions: HashMap<Entity, (Vec2, u32)> = HashMap::default();
+    for (enemy, transform) in q_enemies.iter() {
+        if let Some(core) = enemy.wave_core {
+            let entry = wave_positions.entry(core).or_insert((Vec2::ZERO, 0));
+            entry.0 += transform.translation.truncate();
+            entry.1 += 1;
+        }
+    }
+
+    let mut has_indicator: Vec<Entity> = Vec::new();
+    for (ent, indicator, mut transform, mut visibility, sprite, text) in q_indicators.iter_mut() {
+        let wave = q_wave_cores
+            .get(indicator.wave_core)
+            .ok()
+            .zip(wave_positions.get(&indicator.wave_core));
+        let ((_, wave_core), (position_sum, count)) = match wave {
+            Some(wave) => wave,
+            None => {
+                commands.entity(ent).despawn();
+                continue;
+            }
+        };
+        has_indicator.push(indicator.wave_core);
+
+        let center = *position_sum / *count as f32;
+        let inner_min = view_min + Vec2::splat(INDICATOR_MARGIN);
+        let inner_max = view_max - Vec2::splat(INDICATOR_MARGIN);
+        let on_edge = center.clamp(inner_min, inner_max);
+        visibility.is_visible = center != on_edge;
+
+        let direction = (center - on_edge).normalize_or_zero();
+        let color = wave_color(wave_core.kind, &palette);
+        if let Some(mut sprite) = sprite {
+            transform.translation = on_edge.extend(20.0);
+            transform.rotation = Quat::from_rotation_z(direction.y.atan2(direction.x));
+            transform.scale = Vec3::splat(1.5 + (*count).min(25) as f32 / 25.0);
+            sprite.color = color;
+        }
+        if let Some(mut text) = text {
+            transform.translation = (on_edge - direction * 28.0).extend(20.0);
+            text.sections[0].value = count.to_string();
+            text.sections[0].style.color = color;
+        }
+    }
+
+    for (core, _) in q_wave_cores
+        .iter()
+        .filter(|(core, _)| !has_indicator.contains(core) && wave_positions.contains_key(core))
+    {
+        commands
+            .spawn_bundle(SpriteBundle {
+                texture: sprites.arrow.clone(),
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(OffscreenIndicator { wave_core: core })
+            .insert(InGameUI);
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::with_section(
+                    "",
+                    TextStyle {
+                        font: fonts.main.clone(),
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                    },
+                    TextAlignment {
+                        horizontal: HorizontalAlign::Center,
+                        vertical: VerticalAlign::Center,
+                    },
+                ),
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(OffscreenIndicator { wave_core: core })
+            .insert(InGameUI);
+    }
+}
+
+pub fn spawn_minimap(mut commands: Commands) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(5.0),
+                    right: Val::Px(5.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Px(MINIMAP_WIDTH), Val::Px(MINIMAP_HEIGHT)),
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.5).into(),
+            ..Default::default()
+        })
+        .insert(Ui::Minimap)
+        .insert(InGameUI);
+}
+
+fn minimap_dot(size: f32, color: Color) -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            size: Size::new(Val::Px(size), Val::Px(size)),
+            ..Default::default()
+        },
+        color: color.into(),
+        ..Default::default()
+    }
+}
+
+/// Keeps a marker on the minimap for the lich and every enemy on the battlefield.
+#[allow(clippy::type_complexity)]
+pub fn update_minimap(
+    mut commands: Commands,
+    q_minimap: Query<(Entity, &Ui)>,
+    mut q_markers: Query<(
+        Entity,
+        &MinimapMarker,
+        &mut Style,
+        &mut UiColor,
+        &mut Visibility,
+    )>,
+    q_targets: Query<
+        (Entity, &Transform, Option<&Enemy>, Option<&Velocity>),
+        Or<(With<Player>, With<Enemy>)>,
+    >,
+    q_player: Query<&Transform, With<Player>>,
+    palette: Res<ColorPalette>,
+) {
+    let minimap = match q_minimap.iter().find(|(_, ui)| matches!(ui, Ui::Minimap)) {
+        Some((ent, _)) => ent,
+        None => return,
+    };
+    let player_pos = q_player.iter().next().map(|t| t.translation.truncate());
+    let to_minimap = |pos: Vec2| {
+        (
+            Val::Percent(((pos.x + WORLD_WIDTH / 2.0) / WORLD_WIDTH * 100.0).clamp(0.0, 98.0)),
+            Val::Percent(((pos.y + WORLD_HEIGHT / 2.0) / WORLD_HEIGHT * 100.0).clamp(0.0, 98.0)),
+        )
+    };
+
+    let mut has_marker: Vec<Entity> = Vec::new();
+    for (ent, marker, mut style, mut color, mut visibility) in q_markers.iter_mut() {
+        let (transform, enemy, velocity) = match q_targets.get(marker.target) {
+            Ok((_, transform, enemy, velocity)) => (transform, enemy, velocity),
+            Err(_) => {
+                commands.entity(ent).despawn_recursive();
+                continue;
+            }
+        };
+        has_marker.push(marker.target);
+
+        let pos = transform.translation.truncate();
+        let (left, bottom) = match marker.kind {
+            MinimapMarkerKind::FiringLine(fraction) => {
+                let firing = velocity.map_or(false, |v| v.linear == Vec3::ZERO);
+                visibility.is_visible = firing && player_pos.is_some();
+                to_minimap(pos.lerp(player_pos.unwrap_or(pos), fraction))
+            }
+            _ => to_minimap(pos),
+        };
+        style.position.left = left;
+        style.position.bottom = bottom;
+
+        if let (MinimapMarkerKind::Soldier, Some(enemy)) = (&marker.kind, enemy) {
+            *color = if let EnemyAI::Afraid { speed: _ } = enemy.ai {
+                palette.afraid.into()
+            } else {
+                palette.knights.into()
+            };
+        }
+    }
+
+    for (target, _, enemy, _) in q_targets
+        .iter()
+        .filter(|(target, _, _, _)| !has_marker.contains(target))
+    {
+        let is_player = q_player.get(target).is_ok();
+        let mut firing_line = palette.archers;
+        firing_line.set_a(0.5);
+        let markers: Vec<(MinimapMarkerKind, NodeBundle)> = match enemy.map(|e| &e.ai) {
+            _ if is_player => vec![(MinimapMarkerKind::Lich, minimap_dot(6.0, Color::CYAN))],
+            Some(EnemyAI::Archer { target_y: _ }) => vec![
+                (
+                    MinimapMarkerKind::Archer,
+                    minimap_dot(4.0, wave_color(WaveKind::Archers, &palette)),
+                ),
+                (
+                    MinimapMarkerKind::FiringLine(0.25),
+                    minimap_dot(2.0, firing_line),
+                ),
+                (
+                    MinimapMarkerKind::FiringLine(0.5),
+                    minimap_dot(2.0, firing_line),
+                ),
+                (
+                    MinimapMarkerKind::FiringLine(0.75),
+                    minimap_dot(2.0, firing_line),
+                ),
+            ],
+            Some(_) => vec![(
+                MinimapMarkerKind::Soldier,
+                minimap_dot(4.0, wave_color(WaveKind::Knights, &palette)),
+            )],
+            None => continue,
+        };
+        commands.entity(minimap).with_children(|parent| {
+            for (kind, dot) in markers {
+                parent
+                    .spawn_bundle(dot)
+                    .insert(MinimapMarker { target, kind });
+            }
+        });
+    }
+}
+
+/// Lays out every unlocked spell along the bottom of the screen, along with its hotkey.
+pub fn spawn_spell_bar(
+    mut commands: Commands,
+    sprites: Res<GameSprites>,
+    fonts: Res<GameFonts>,
+    run_state: Res<RunState>,
+    input_map: Res<InputMap>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(8.0),
+                    left: Val::Px(0.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(100.0), Val::Px(SPELL_SLOT_SIZE)),
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(InGameUI)
+        .with_children(|parent| {
+            for (slot, spell) in run_state.known_spells().into_iter().enumerate() {
+                let (icon, tint) = spell.icon(&sprites);
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(SPELL_SLOT_SIZE), Val::Px(SPELL_SLOT_SIZE)),
+                            margin: Rect::all(Val::Px(4.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        color: SPELL_SLOT_NORMAL.into(),
+                        ..Default::default()
+                    })
+                    .insert(SpellBarElement::Slot(spell))
+                    .with_children(|parent| {
+                        parent
+                            .spawn_bundle(ImageBundle {
+                                style: Style {
+                                    size: Size::new(
+                                        Val::Px(SPELL_ICON_SIZE),
+                                        Val::Px(SPELL_ICON_SIZE),
+                                    ),
+                                    ..Default::default()
+                                },
+                                image: UiImage(icon),
+                                color: tint.into(),
+                                ..Default::default()
+                            })
+                            .insert(SpellBarElement::Icon(spell));
+
+                        // The sweep starts at the top and runs clockwise
+                        for index in 0..SPELL_SWEEP_DOTS {
+                            let angle =
+                                index as f32 / SPELL_SWEEP_DOTS as f32 * std::f32::consts::TAU;
+                            let center = SPELL_SLOT_SIZE / 2.0 - 2.0;
+                            parent
+                                .spawn_bundle(NodeBundle {
+                                    style: Style {
+                                        position_type: PositionType::Absolute,
+                                        position: Rect {
+                                            left: Val::Px(
+                                                center + angle.sin() * SPELL_SWEEP_RADIUS,
+                                            ),
+                                            bottom: Val::Px(
+                                                center + angle.cos() * SPELL_SWEEP_RADIUS,
+                                            ),
+                                            ..Default::default()
+                                        },
+                                        size: Size::new(Val::Px(4.0), Val::Px(4.0)),
+                                        ..Default::default()
+                                    },
+                                    color: Color::WHITE.into(),
+                                    ..Default::default()
+                                })
+                                .insert(SpellBarElement::Sweep { spell, index });
+                        }
+
+                        parent.spawn_bundle(TextBundle {
+                            style: Style {
+                                position_type: PositionType::Absolute,
+                                position: Rect {
+                                    left: Val::Px(3.0),
+                                    bottom: Val::Px(SPELL_SLOT_SIZE - 16.0),
+                                    ..Default::default()
+                                },
+                                ..Default::default()
+                            },
+                            text: Text::with_section(
+                                input_map.binding_name(
+                                    Action::SelectSpell(slot),
+                                    InputDevice::KeyboardMouse,
+                                ),
+                                TextStyle {
+                                    font: fonts.main.clone(),
+                                    font_size: 16.0,
+                                    color: Color::WHITE,
+                                },
+                                Default::default(),
+                            ),
+                            ..Default::default()
+                        });
+                    });
+            }
+        });
+}
+
+/// Highlights the selected spell, dims the ones that can't be afforded,
+/// and sweeps each slot's ring of dots around as its cooldown recovers.
+pub fn update_spell_bar(
+    q_player: Query<(&PlayerSpellData, &Mana), With<Player>>,
+    mut q_elements: Query<(&SpellBarElement, &mut UiColor)>,
+    sprites: Res<GameSprites>,
+) {
+    let (spell_data, mana) = match q_player.iter().next() {
+        Some(player) => player,
+        None => return,
+    };
+
+    for (element, mut color) in q_elements.iter_mut() {
+        *color = match *element {
+            SpellBarElement::Slot(spell) if spell == spell_data.selected => {
+                SPELL_SLOT_SELECTED.into()
+            }
+            SpellBarElement::Slot(_) => SPELL_SLOT_NORMAL.into(),
+            SpellBarElement::Icon(spell) => {
+                let (_, tint) = spell.icon(&sprites);
+                if mana.current >= spell.mana_cost() {
+                    tint.into()
+                } else {
+                    (tint * 0.4).into()
+                }
+            }
+            SpellBarElement::Sweep { spell, index } => {
+                let cooldown = spell_data.cooldowns.for_spell(spell);
+                let recovered = if cooldown.finished() {
+                    SPELL_SWEEP_DOTS
+                } else {
+                    (cooldown.percent() * SPELL_SWEEP_DOTS as f32) as usize
+                };
+                if index < recovered {
+                    Color::WHITE.into()
+                } else {
+                    Color::rgba(0.3, 0.3, 0.3, 0.6).into()
+                }
+            }
+        };
+    }
+}
+
+/// Draws the ring used by area spell reticles, since there is no sprite for it.
+pub fn generate_reticle_texture(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let size = RETICLE_TEXTURE_SIZE;
+    let center = size as f32 / 2.0;
+    let radius = center - RETICLE_THICKNESS;
+    let mut data = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let distance = Vec2::new(x as f32 + 0.5 - center, y as f32 + 0.5 - center).length();
+            let alpha = if (distance - radius).abs() <= RETICLE_THICKNESS / 2.0 {
+                255
+            } else {
+                0
+            };
+            data.extend_from_slice(&[255, 255, 255, alpha]);
+        }
+    }
+    let image = Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    commands.insert_resource(ReticleTexture(images.add(image)));
+}
+
+pub fn spawn_reticle(mut commands: Commands, texture: Res<ReticleTexture>) {
+    let parts = [
+        ReticlePart::Ring,
+        ReticlePart::SplashRing,
+        ReticlePart::Strip,
+        ReticlePart::Spread(-1),
+        ReticlePart::Spread(0),
+        ReticlePart::Spread(1),
+    ];
+    for part in parts {
+        let mut bundle = SpriteBundle {
+            transform: Transform::from_xyz(0.0, 0.0, 14.0),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        };
+        // Lines are drawn with the default blank texture
+        if let ReticlePart::Ring | ReticlePart::SplashRing = part {
+            bundle.texture = texture.0.clone();
+        }
+        commands.spawn_bundle(bundle).insert(part).insert(InGameUI);
+    }
+}
+
+/// The radius of the area a spell lands in, and of any extra splash around it.
+fn reticle_radii(spell: PlayerSpell) -> (Option<f32>, Option<f32>) {
+    match spell {
+        PlayerSpell::LightningStrike => {
+            let strike = LightningStrikeBolt::strike(0.0);
+            (
+                Some(strike.radius),
+                strike.splash.map(|(splash, _)| strike.radius + splash),
+            )
+        }
+        PlayerSpell::ChainLightning => (Some(CHAIN_LIGHTNING_RADIUS), None),
+        PlayerSpell::LightningStorm => (Some(LIGHTNING_STORM_RADIUS), None),
+        PlayerSpell::RaiseDead => (Some(RAISE_DEAD_RADIUS), None),
+        PlayerSpell::Fireball | PlayerSpell::FearWave => (None, None),
+    }
+}
+
+/// Shapes the reticle after the selected spell, tinting it red while that spell is recovering.
+#[allow(clippy::type_complexity)]
+pub fn update_reticle(
+    mut q_parts: Query<
+        (&ReticlePart, &mut Transform, &mut Sprite, &mut Visibility),
+        Without<Player>,
+    >,
+    q_player: Query<(&Transform, &PlayerSpellData), (With<Player>, Without<Reforming>)>,
+    player_aim: Res<PlayerAim>,
+    sprites: Res<GameSprites>,
+) {
+    let target = q_player.iter().next().zip(player_aim.0);
+    let (player_t, spell_data, cursor_pos) = match target {
+        Some(((player_t, spell_data), cursor_pos)) => (player_t, spell_data, cursor_pos),
+        None => {
+            for (_, _, _, mut visibility) in q_parts.iter_mut() {
+                visibility.is_visible = false;
+            }
+            return;
+        }
+    };
+
+    let spell = spell_data.selected;
+    let color = if spell_data.cooldowns.for_spell(spell).finished() {
+        let (_, mut tint) = spell.icon(&sprites);
+        tint.set_a(0.5);
+        tint
+    } else {
+        RETICLE_COOLDOWN
+    };
+    let (radius, splash_radius) = reticle_radii(spell);
+    let player_pos = player_t.translation.truncate();
+    let aim = (cursor_pos - player_pos).extend(0.0).normalize_or_zero();
+
+    for (part, mut transform, mut sprite, mut visibility) in q_parts.iter_mut() {
+        let shape = match (*part, spell) {
+            (ReticlePart::Ring, _) => radius.map(|r| (cursor_pos, 0.0, Vec2::splat(r * 2.0))),
+            (ReticlePart::SplashRing, _) => {
+                splash_radius.map(|r| (cursor_pos, 0.0, Vec2::splat(r * 2.0)))
+            }
+            (ReticlePart::Strip, PlayerSpell::FearWave) => Some((
+                cursor_pos,
+                aim.y.atan2(aim.x),
+                // Charging widens the wave
+                Vec2::new(
+                    RETICLE_THICKNESS,
+                    FEAR_WAVE_WIDTH * (1.0 + spell_data.charge.unwrap_or(0.0) / MAX_CHARGE),
+                ),
+            )),
+            (ReticlePart::Spread(step), PlayerSpell::Fireball) => {
+                let direction = aim.rotate_2d(FIREBALL_SPREAD * step as f32).truncate();
+                let length = player_pos.distance(cursor_pos).min(RETICLE_SPREAD_LENGTH);
+                Some((
+                    player_pos + direction * length / 2.0,
+                    direction.y.atan2(direction.x),
+                    Vec2::new(length, 2.0),
+                ))
+            }
+            _ => None,
+        };
+
+        visibility.is_visible = shape.is_some();
+        if let Some((position, angle, size)) = shape {
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+            transform.rotation = Quat::from_rotation_z(angle);
+            sprite.custom_size = Some(size);
+            sprite.color = color;
+            if let ReticlePart::SplashRing = part {
+                // The splash only grazes, so it is drawn fainter
+                sprite.color.set_a(color.a() * 0.5);
+            }
+        }
+    }
+}
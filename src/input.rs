@@ -0,0 +1,149 @@
+use crate::{
+    common::{
+        get_cursor_position, Action, ActiveGamepad, InputBinding, InputDevice, InputMap,
+        MainCamera, Player, PlayerAim, Reforming, STICK_DEADZONE,
+    },
+    config::save_config,
+};
+use bevy::{input::mouse::MouseMotion, prelude::*};
+
+/// The config file the bindings are kept in.
+pub const CONTROLS_CONFIG: &str = "controls";
+
+/// How far from the lich the right stick aims when tilted all the way.
+const STICK_AIM_DISTANCE: f32 = 320.0;
+/// How far from the lich the right stick aims when only just tilted.
+const STICK_AIM_MIN_DISTANCE: f32 = 64.0;
+
+/// Picks up gamepads as they are plugged in, and lets go of them when they are pulled out.
+pub fn handle_gamepad_connections(
+    mut gamepad_events: EventReader<GamepadEvent>,
+    mut active_gamepad: ResMut<ActiveGamepad>,
+    mut device: ResMut<InputDevice>,
+) {
+    for GamepadEvent(gamepad, event_type) in gamepad_events.iter() {
+        match event_type {
+            GamepadEventType::Connected => {
+                if active_gamepad.0.is_none() {
+                    active_gamepad.0 = Some(*gamepad);
+                }
+            }
+            GamepadEventType::Disconnected => {
+                if active_gamepad.0 == Some(*gamepad) {
+                    active_gamepad.0 = None;
+                    *device = InputDevice::KeyboardMouse;
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Presses and releases each action to match whether any of its bindings are held.
+pub fn update_action_state(
+    mut actions: ResMut<Input<Action>>,
+    input_map: Res<InputMap>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    active_gamepad: Res<ActiveGamepad>,
+) {
+    actions.clear();
+    for action in Action::ALL {
+        let on_keyboard = input_map
+            .keyboard
+            .get(&action)
+            .map_or(false, |binding| match binding {
+                InputBinding::Key(key) => keyboard_input.pressed(*key),
+                InputBinding::Mouse(button) => mouse_input.pressed(*button),
+            });
+        let on_gamepad = input_map.gamepad.get(&action).map_or(false, |button| {
+            active_gamepad.pressed(&gamepad_buttons, *button)
+        });
+
+        if on_keyboard || on_gamepad {
+            actions.press(action);
+        } else if actions.pressed(action) {
+            actions.release(action);
+        }
+    }
+}
+
+/// Writes the bindings back out whenever they're changed.
+pub fn save_input_map(input_map: Res<InputMap>) {
+    if input_map.is_changed() && !input_map.is_added() {
+        save_config(CONTROLS_CONFIG, &*input_map);
+    }
+}
+
+/// Switches the input device whenever the other one is used, so prompts follow the player.
+pub fn detect_input_device(
+    mut gamepad_events: EventReader<GamepadEvent>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    active_gamepad: Res<ActiveGamepad>,
+    mut device: ResMut<InputDevice>,
+) {
+    let used_gamepad = gamepad_events
+        .iter()
+        .any(|GamepadEvent(gamepad, event_type)| {
+            active_gamepad.0 == Some(*gamepad)
+                && match event_type {
+                    GamepadEventType::ButtonChanged(_, value) => *value > 0.5,
+                    GamepadEventType::AxisChanged(_, value) => value.abs() > STICK_DEADZONE,
+                    _ => false,
+                }
+        });
+    let used_keyboard = keyboard_input.get_just_pressed().next().is_some()
+        || mouse_input.get_just_pressed().next().is_some()
+        || mouse_motion.iter().count() > 0;
+
+    let current = if used_gamepad {
+        InputDevice::Gamepad
+    } else if used_keyboard {
+        InputDevice::KeyboardMouse
+    } else {
+        return;
+    };
+    // Only write on a real switch, so anything watching for changes stays quiet
+    if *device != current {
+        *device = current;
+    }
+}
+
+/// Works out where the lich is aiming: at the cursor, or out along the right stick.
+#[allow(clippy::too_many_arguments)]
+pub fn update_player_aim(
+    mut aim: ResMut<PlayerAim>,
+    mut stick_offset: Local<Vec2>,
+    device: Res<InputDevice>,
+    active_gamepad: Res<ActiveGamepad>,
+    axes: Res<Axis<GamepadAxis>>,
+    q_player: Query<&Transform, (With<Player>, Without<Reforming>)>,
+    wnds: Res<Windows>,
+    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    aim.0 = match *device {
+        InputDevice::KeyboardMouse => get_cursor_position(wnds, q_camera),
+        InputDevice::Gamepad => {
+            let stick = active_gamepad.stick(
+                &axes,
+                GamepadAxisType::RightStickX,
+                GamepadAxisType::RightStickY,
+            );
+            // Letting go of the stick keeps aiming wherever it last pointed
+            if stick != Vec2::ZERO {
+                *stick_offset = stick.normalize()
+                    * (STICK_AIM_MIN_DISTANCE
+                        + (STICK_AIM_DISTANCE - STICK_AIM_MIN_DISTANCE) * stick.length());
+            } else if *stick_offset == Vec2::ZERO {
+                *stick_offset = Vec2::new(0.0, STICK_AIM_MIN_DISTANCE);
+            }
+            q_player
+                .iter()
+                .next()
+                .map(|player_t| player_t.translation.truncate() + *stick_offset)
+        }
+    };
+}
@@ -1,10 +1,19 @@
 use bevy::prelude::*;
 
+mod arena;
+mod camera;
+mod collision;
 mod common;
+mod config;
 mod enemy;
+mod hud;
+mod input;
 mod menu;
+mod minion;
+mod phylactery;
 mod player;
 mod projectile;
+mod settings;
 mod setup;
 
 fn main() {
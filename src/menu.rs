@@ -1,10 +1,14 @@
 use crate::common::{
-    CurrentDay, DayEndReason, EndDayEvent, EnemyMorale, GameAudio, GameFonts, GameOverButton,
-    GameSprites, GameState, MainMenuButton, NarrationViewed, OpeningNarration, Ui,
+    Action, ActiveGamepad, BackButton, ControlsButton, ControlsScreen, CurrentDay, DayEndReason,
+    DayReport, EndDayEvent, EnemyMorale, GameAudio, GameFonts, GameOverButton, GameSprites,
+    GameState, InputBinding, InputDevice, InputMap, MainMenuButton, MenuFocus, MinionOrders,
+    NarrationViewed, OpeningNarration, PauseButton, PlayerSpell, RunState, Setting, Settings,
+    SettingsButton, ShopButton, SpellUnlockCutscene, Ui, Upgrade,
 };
-use bevy::prelude::*;
+use bevy::{prelude::*, window::WindowFocused};
 use bevy_ecs_tilemap::prelude::*;
 use bevy_kira_audio::Audio;
+use heron::PhysicsTime;
 
 const NARRATION_LENGTH: usize = 2;
 const OPENING_NARRATION: [&str; NARRATION_LENGTH] = [
@@ -60,6 +64,8 @@ pub fn button_main_menu(
     audio: Res<GameAudio>,
     audio_player: Res<Audio>,
     mut narration_viewed: ResMut<NarrationViewed>,
+    mut run_state: ResMut<RunState>,
+    mut minion_orders: ResMut<MinionOrders>,
 ) {
     for (interaction, mut color, button_type) in q_interaction.iter_mut() {
         match *interaction {
@@ -69,6 +75,8 @@ pub fn button_main_menu(
                     MainMenuButton::Start => {
                         current_day.day = 0;
                         morale.current = 50.0;
+                        *run_state = RunState::default();
+                        *minion_orders = MinionOrders::default();
                         if !narration_viewed.0 {
                             narration_viewed.0 = true;
                             state.set(GameState::Opening).unwrap();
@@ -76,6 +84,12 @@ pub fn button_main_menu(
                             state.set(GameState::MoraleStatus).unwrap();
                         }
                     }
+                    MainMenuButton::Controls => {
+                        state.set(GameState::Controls).unwrap();
+                    }
+                    MainMenuButton::Settings => {
+                        state.set(GameState::Settings).unwrap();
+                    }
                     MainMenuButton::Credits => {
                         state.set(GameState::Credits).unwrap();
                     }
@@ -159,6 +173,8 @@ pub fn spawn_main_menu(mut commands: Commands, fonts: Res<GameFonts>, sprites: R
                         };
 
                     spawn_button(parent, "Start", MainMenuButton::Start);
+                    spawn_button(parent, "Controls", MainMenuButton::Controls);
+                    spawn_button(parent, "Settings", MainMenuButton::Settings);
                     spawn_button(parent, "Credits", MainMenuButton::Credits);
                 });
         });
@@ -283,8 +299,14 @@ pub fn button_start_day(
                     state.set(GameState::GameOver).unwrap();
                 } else {
                     current_day.player_damaged = 0.0;
+                    current_day.deaths = 0;
                     current_day.day += 1;
-                    state.set(GameState::ActiveGame).unwrap();
+                    // There are no souls to spend before the first day
+                    if current_day.day > 1 {
+                        state.set(GameState::Shop).unwrap();
+                    } else {
+                        state.set(GameState::ActiveGame).unwrap();
+                    }
                 }
             }
             Interaction::Hovered => {
@@ -297,12 +319,42 @@ pub fn button_start_day(
     }
 }
 
+/// Hands out whatever the day earned as it ends: harvested souls, a fireball modifier,
+/// and any spells due by the coming day. Leaving the day any other way earns nothing.
+pub fn settle_day_end(
+    mut day_end_reader: EventReader<EndDayEvent>,
+    morale: Res<EnemyMorale>,
+    current_day: Res<CurrentDay>,
+    mut run_state: ResMut<RunState>,
+    mut report: ResMut<DayReport>,
+) {
+    let ended = day_end_reader
+        .iter()
+        .any(|e| !matches!(e.reason, DayEndReason::PhylacteryDestroyed));
+    if !ended {
+        return;
+    }
+
+    // Every soldier slain today leaves behind a soul to spend in the shop
+    run_state.souls += morale.enemies_killed;
+    *report = DayReport {
+        souls_harvested: morale.enemies_killed,
+        modifier_unlocked: run_state.modifiers.unlock_for_day(current_day.day),
+        // Spells due by the coming day are learned overnight
+        new_spells: run_state.unlock_spells_for_day(current_day.day + 1),
+    };
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_morale_status(
     mut commands: Commands,
     fonts: Res<GameFonts>,
+    sprites: Res<GameSprites>,
     mut morale: ResMut<EnemyMorale>,
     current_day: Res<CurrentDay>,
     mut day_end_reader: EventReader<EndDayEvent>,
+    run_state: Res<RunState>,
+    report: Res<DayReport>,
 ) {
     let day_end = day_end_reader.iter().next();
 
@@ -355,11 +407,27 @@ Your phylactery keeps you alive,
 but the army celebrates its victory.\n\n"
                 }
             }
+            DayEndReason::PhylacteryDestroyed => {
+                "Your phylactery lies shattered.
+Nothing binds you to this world anymore.\n\n"
+            }
         }
     } else {
         ""
     };
 
+    // Only possible when the lich reforms at its phylactery instead of ending the day
+    let deaths_text = match day_end {
+        Some(EndDayEvent {
+            reason: DayEndReason::Timeout,
+        }) if current_day.deaths > 0 => format!(
+            "You were struck down {} time(s) today,
+and each time the army grew bolder.\n\n",
+            current_day.deaths
+        ),
+        _ => "".to_string(),
+    };
+
     let morale_text_end = if current_day.day == 0 {
         "\nThe next army is about to arrive..."
     } else if morale.current == 0.0 || morale.current == 100.0 {
@@ -377,6 +445,21 @@ allow them to hurt you, or escape your wrath."
 You should maintain this balance."
     };
 
+    let game_over = morale.current == 0.0 || morale.current == 100.0;
+    let new_spells = if day_end.is_some() && !game_over {
+        report.new_spells.clone()
+    } else {
+        Vec::new()
+    };
+    let unlock_text = if day_end.is_some() && !game_over {
+        format!(
+            "\n\n{}\nYou harvested {} souls today, and now hold {}.",
+            report.modifier_unlocked, report.souls_harvested, run_state.souls
+        )
+    } else {
+        "".to_string()
+    };
+
     let button_text = if current_day.day == 0 {
         "Start Day"
     } else if morale.current == 0.0 || morale.current == 100.0 {
@@ -396,6 +479,7 @@ You should maintain this balance."
 
     morale.change = 0.0;
     morale.enemies_killed = 0;
+    morale.minion_kills = 0;
 
     commands
         .spawn_bundle(NodeBundle {
@@ -416,7 +500,7 @@ You should maintain this balance."
                     text: Text {
                         sections: vec![
                             TextSection {
-                                value: morale_text_prelude.to_string(),
+                                value: format!("{}{}", deaths_text, morale_text_prelude),
                                 style: TextStyle {
                                     font: fonts.main.clone(),
                                     font_size: 32.0,
@@ -447,6 +531,14 @@ You should maintain this balance."
                                     color: TEXT_COLOR,
                                 },
                             },
+                            TextSection {
+                                value: unlock_text,
+                                style: TextStyle {
+                                    font: fonts.main.clone(),
+                                    font_size: 32.0,
+                                    color: Color::rgb(1.0, 0.8, 0.4),
+                                },
+                            },
                             TextSection {
                                 value: game_tip,
                                 style: TextStyle {
@@ -495,10 +587,123 @@ You should maintain this balance."
                     });
                 });
         });
+
+    if !new_spells.is_empty() {
+        spawn_spell_unlock_cutscene(&mut commands, &fonts, &sprites, &new_spells);
+    }
+}
+
+fn spawn_spell_unlock_cutscene(
+    commands: &mut Commands,
+    fonts: &GameFonts,
+    sprites: &GameSprites,
+    spells: &[PlayerSpell],
+) {
+    let names = spells
+        .iter()
+        .map(|s| s.name())
+        .collect::<Vec<_>>()
+        .join(", ");
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.9).into(),
+            ..Default::default()
+        })
+        .insert(Ui::Core)
+        .insert(SpellUnlockCutscene(Timer::from_seconds(3.0, false)))
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "New power stirs within you...",
+                    TextStyle {
+                        font: fonts.main.clone(),
+                        font_size: 32.0,
+                        color: TEXT_COLOR,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        size: Size::new(Val::Auto, Val::Px(128.0)),
+                        ..Default::default()
+                    },
+                    color: Color::NONE.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for spell in spells {
+                        let (icon, tint) = spell.icon(sprites);
+                        parent
+                            .spawn_bundle(ImageBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(0.0), Val::Px(0.0)),
+                                    margin: Rect::all(Val::Px(16.0)),
+                                    ..Default::default()
+                                },
+                                image: UiImage(icon),
+                                color: tint.into(),
+                                ..Default::default()
+                            })
+                            .insert(Ui::SpellUnlockIcon);
+                    }
+                });
+
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    format!("You have learned {}", names),
+                    TextStyle {
+                        font: fonts.main.clone(),
+                        font_size: 64.0,
+                        color: Color::WHITE,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+/// Grows the newly learned spell icons in, then fades the cutscene away.
+pub fn animate_spell_unlock(
+    mut commands: Commands,
+    mut q_cutscene: Query<(Entity, &mut SpellUnlockCutscene, &mut UiColor)>,
+    mut q_icons: Query<(&Ui, &mut Style)>,
+    time: Res<Time>,
+) {
+    for (ent, mut cutscene, mut color) in q_cutscene.iter_mut() {
+        if cutscene.0.tick(time.delta()).finished() {
+            commands.entity(ent).despawn_recursive();
+            continue;
+        }
+        let grow = cutscene.0.elapsed_secs().min(1.0);
+        let size = 96.0 * (1.0 - (1.0 - grow).powi(3));
+        for (ui, mut style) in q_icons.iter_mut() {
+            if let Ui::SpellUnlockIcon = ui {
+                style.size = Size::new(Val::Px(size), Val::Px(size));
+            }
+        }
+        let remaining = cutscene.0.duration().as_secs_f32() - cutscene.0.elapsed_secs();
+        color.0.set_a(0.9 * (remaining / 0.5).min(1.0));
+    }
 }
 
 // Game over
 
+#[allow(clippy::too_many_arguments)]
 #[allow(clippy::type_complexity)]
 pub fn button_game_over(
     mut q_interaction: Query<
@@ -510,6 +715,8 @@ pub fn button_game_over(
     mut current_day: ResMut<CurrentDay>,
     audio: Res<GameAudio>,
     audio_player: Res<Audio>,
+    mut run_state: ResMut<RunState>,
+    mut minion_orders: ResMut<MinionOrders>,
 ) {
     for (interaction, mut color, button_type) in q_interaction.iter_mut() {
         match *interaction {
@@ -519,6 +726,8 @@ pub fn button_game_over(
                     GameOverButton::Restart => {
                         current_day.day = 0;
                         morale.current = 50.0;
+                        *run_state = RunState::default();
+                        *minion_orders = MinionOrders::default();
                         state.set(GameState::MoraleStatus).unwrap();
                     }
                     GameOverButton::MainMenu => {
@@ -541,8 +750,18 @@ pub fn spawn_game_over(
     fonts: Res<GameFonts>,
     morale: Res<EnemyMorale>,
     current_day: Res<CurrentDay>,
+    mut day_end_reader: EventReader<EndDayEvent>,
 ) {
-    let game_over_narration = if morale.current == 100.0 {
+    let phylactery_destroyed = day_end_reader
+        .iter()
+        .any(|e| matches!(e.reason, DayEndReason::PhylacteryDestroyed));
+    let game_over_narration = if phylactery_destroyed {
+        "While you were busy on the battlefield,
+a band of raiders slipped past you.
+\nYour phylactery shatters under their blows,
+and your soul, with nothing left to bind it,
+is torn from this world for good."
+    } else if morale.current == 100.0 {
         "Recent victories have granted bravery to humanity.
 \nInvigorated, they begin truly pushing you back,
 giving you defeat after defeat.
@@ -624,38 +843,39 @@ and you will perish from hunger."
                 .with_children(|parent| {
                     let spawn_button =
                         |parent: &mut ChildBuilder, text: &str, context: GameOverButton| {
-                            parent
-                                .spawn_bundle(ButtonBundle {
-                                    style: Style {
-                                        size: Size::new(Val::Px(150.0), Val::Px(65.0)),
-                                        margin: Rect {
-                                            top: Val::Px(30.0),
-                                            left: Val::Px(30.0),
-                                            right: Val::Px(30.0),
-                                            ..Default::default()
-                                        },
-                                        justify_content: JustifyContent::Center,
-                                        align_items: AlignItems::Center,
+                            let mut button = parent.spawn_bundle(ButtonBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+                                    margin: Rect {
+                                        top: Val::Px(30.0),
+                                        left: Val::Px(30.0),
+                                        right: Val::Px(30.0),
                                         ..Default::default()
                                     },
-                                    color: BUTTON_NORMAL.into(),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..Default::default()
+                                },
+                                color: BUTTON_NORMAL.into(),
+                                ..Default::default()
+                            });
+                            if let GameOverButton::MainMenu = context {
+                                button.insert(BackButton);
+                            }
+                            button.insert(context).with_children(|parent| {
+                                parent.spawn_bundle(TextBundle {
+                                    text: Text::with_section(
+                                        text.to_string(),
+                                        TextStyle {
+                                            font: fonts.main.clone(),
+                                            font_size: 32.0,
+                                            color: Color::WHITE,
+                                        },
+                                        Default::default(),
+                                    ),
                                     ..Default::default()
-                                })
-                                .insert(context)
-                                .with_children(|parent| {
-                                    parent.spawn_bundle(TextBundle {
-                                        text: Text::with_section(
-                                            text.to_string(),
-                                            TextStyle {
-                                                font: fonts.main.clone(),
-                                                font_size: 32.0,
-                                                color: Color::WHITE,
-                                            },
-                                            Default::default(),
-                                        ),
-                                        ..Default::default()
-                                    });
                                 });
+                            });
                         };
 
                     spawn_button(parent, "Restart", GameOverButton::Restart);
@@ -664,37 +884,43 @@ and you will perish from hunger."
         });
 }
 
-// Credits
+// Shop
 
-#[allow(clippy::type_complexity)]
-pub fn button_credits_back(
-    mut q_interaction: Query<(&Interaction, &mut UiColor), (Changed<Interaction>, With<Button>)>,
-    mut state: ResMut<State<GameState>>,
-    audio: Res<GameAudio>,
-    audio_player: Res<Audio>,
-) {
-    for (interaction, mut color) in q_interaction.iter_mut() {
-        match *interaction {
-            Interaction::Clicked => {
-                audio_player.play(audio.click.clone());
-                state.set(GameState::MainMenu).unwrap();
-            }
-            Interaction::Hovered => {
-                *color = BUTTON_HOVER.into();
-            }
-            Interaction::None => {
-                *color = BUTTON_NORMAL.into();
-            }
-        }
-    }
+fn upgrade_label(run_state: &RunState, upgrade: Upgrade) -> String {
+    let level = run_state.upgrades.level(upgrade);
+    let cost = match run_state.upgrades.cost(upgrade) {
+        Some(cost) => format!("{} souls", cost),
+        None => "Maxed".to_string(),
+    };
+    format!(
+        "{}\n{}\nLv {}/{} - {}",
+        upgrade.name(),
+        upgrade.description(),
+        level,
+        Upgrade::MAX_LEVEL,
+        cost
+    )
 }
 
-pub fn spawn_credits(mut commands: Commands, fonts: Res<GameFonts>, sprites: Res<GameSprites>) {
+fn spell_label(run_state: &RunState, spell: PlayerSpell) -> String {
+    let status = if run_state.unlocked_spells.contains(&spell) {
+        "Learned".to_string()
+    } else {
+        format!(
+            "{} souls\n(free on day {})",
+            spell.soul_cost().unwrap_or(0),
+            spell.unlock_day()
+        )
+    };
+    format!("{}\n{}", spell.name(), status)
+}
+
+pub fn spawn_shop(mut commands: Commands, fonts: Res<GameFonts>, run_state: Res<RunState>) {
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
                 size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
-                justify_content: JustifyContent::SpaceBetween,
+                justify_content: JustifyContent::Center,
                 align_items: AlignItems::Center,
                 flex_direction: FlexDirection::ColumnReverse,
                 ..Default::default()
@@ -706,85 +932,85 @@ pub fn spawn_credits(mut commands: Commands, fonts: Res<GameFonts>, sprites: Res
         .with_children(|parent| {
             parent
                 .spawn_bundle(TextBundle {
-                    text: Text {
-                        sections: vec![
-                            TextSection {
-                                value: "Credits\n".to_string(),
-                                style: TextStyle {
-                                    font: fonts.main.clone(),
-                                    font_size: 64.0,
-                                    color: Color::WHITE,
-                                },
-                            },
-                            TextSection {
-                                value: "\nAssets:\nFont: ".to_string(),
-                                style: TextStyle {
-                                    font: fonts.main.clone(),
-                                    font_size: 32.0,
-                                    color: Color::WHITE,
-                                },
-                            },
-                            TextSection {
-                                value: "m5x7 by Daniel Linssen\nhttps://managore.itch.io/m5x7".to_string(),
-                                style: TextStyle {
-                                    font: fonts.main.clone(),
-                                    font_size: 32.0,
-                                    color: TEXT_COLOR,
-                                },
-                            },
-                            TextSection {
-                                value: "\nEnemy and Player Sprites: ".to_string(),
-                                style: TextStyle {
-                                    font: fonts.main.clone(),
-                                    font_size: 32.0,
-                                    color: Color::WHITE,
-                                },
-                            },
-                            TextSection {
-                                value: "16x16 DungeonTileset II by 0x72\nhttps://0x72.itch.io/dungeontileset-ii\n".to_string(),
-                                style: TextStyle {
-                                    font: fonts.main.clone(),
-                                    font_size: 32.0,
-                                    color: TEXT_COLOR,
-                                },
-                            },
-                            TextSection {
-                                value: "\n\nGame made by ".to_string(),
-                                style: TextStyle {
-                                    font: fonts.main.clone(),
-                                    font_size: 32.0,
-                                    color: TEXT_COLOR,
-                                },
-                            },
-                            TextSection {
-                                value: "ProspectPyxis".to_string(),
-                                style: TextStyle {
-                                    font: fonts.main.clone(),
-                                    font_size: 32.0,
-                                    color: Color::WHITE,
-                                },
-                            },
-                        ],
-                        alignment: TextAlignment {
+                    text: Text::with_section(
+                        format!("Souls: {}", run_state.souls),
+                        TextStyle {
+                            font: fonts.main.clone(),
+                            font_size: 64.0,
+                            color: Color::WHITE,
+                        },
+                        TextAlignment {
                             horizontal: HorizontalAlign::Center,
                             vertical: VerticalAlign::Center,
                         },
-                    },
+                    ),
                     ..Default::default()
                 })
-                .insert(Ui::NarrationText);
+                .insert(Ui::SoulsDisplay);
 
-            parent.spawn_bundle(ImageBundle {
-                image: UiImage(sprites.bevy.clone()),
-                transform: Transform::from_scale(Vec3::new(0.5, 0.5, 0.0)),
-                style: Style {
-                    margin: Rect {
-                        top: Val::Px(30.0),
+            let spawn_button = |parent: &mut ChildBuilder, text: String, context: ShopButton| {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(176.0), Val::Px(96.0)),
+                            margin: Rect::all(Val::Px(4.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        color: BUTTON_NORMAL.into(),
                         ..Default::default()
-                    },
+                    })
+                    .insert(context)
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                text,
+                                TextStyle {
+                                    font: fonts.main.clone(),
+                                    font_size: 16.0,
+                                    color: TEXT_COLOR,
+                                },
+                                TextAlignment {
+                                    horizontal: HorizontalAlign::Center,
+                                    vertical: VerticalAlign::Center,
+                                },
+                            ),
+                            ..Default::default()
+                        });
+                    });
+            };
+            let row = NodeBundle {
+                style: Style {
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
                     ..Default::default()
                 },
+                color: Color::NONE.into(),
                 ..Default::default()
+            };
+
+            parent.spawn_bundle(row.clone()).with_children(|parent| {
+                for upgrade in Upgrade::ALL {
+                    spawn_button(
+                        parent,
+                        upgrade_label(&run_state, upgrade),
+                        ShopButton::Buy(upgrade),
+                    );
+                }
+            });
+
+            parent.spawn_bundle(row).with_children(|parent| {
+                for spell in PlayerSpell::ALL
+                    .into_iter()
+                    .filter(|s| s.soul_cost().is_some())
+                {
+                    spawn_button(
+                        parent,
+                        spell_label(&run_state, spell),
+                        ShopButton::Learn(spell),
+                    );
+                }
             });
 
             parent
@@ -793,7 +1019,6 @@ pub fn spawn_credits(mut commands: Commands, fonts: Res<GameFonts>, sprites: Res
                         size: Size::new(Val::Px(150.0), Val::Px(65.0)),
                         margin: Rect {
                             top: Val::Px(30.0),
-                            bottom: Val::Px(30.0),
                             ..Default::default()
                         },
                         justify_content: JustifyContent::Center,
@@ -803,10 +1028,11 @@ pub fn spawn_credits(mut commands: Commands, fonts: Res<GameFonts>, sprites: Res
                     color: BUTTON_NORMAL.into(),
                     ..Default::default()
                 })
+                .insert(ShopButton::StartDay)
                 .with_children(|parent| {
                     parent.spawn_bundle(TextBundle {
                         text: Text::with_section(
-                            "Back",
+                            "Start Day",
                             TextStyle {
                                 font: fonts.main.clone(),
                                 font_size: 32.0,
@@ -820,6 +1046,1058 @@ pub fn spawn_credits(mut commands: Commands, fonts: Res<GameFonts>, sprites: Res
         });
 }
 
+#[allow(clippy::type_complexity)]
+pub fn button_shop(
+    mut q_interaction: Query<
+        (&Interaction, &mut UiColor, &ShopButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut state: ResMut<State<GameState>>,
+    mut run_state: ResMut<RunState>,
+    audio: Res<GameAudio>,
+    audio_player: Res<Audio>,
+) {
+    for (interaction, mut color, button_type) in q_interaction.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => match *button_type {
+                ShopButton::Buy(upgrade) => {
+                    if run_state.buy(upgrade) {
+                        audio_player.play(audio.click.clone());
+                    }
+                }
+                ShopButton::Learn(spell) => {
+                    if run_state.buy_spell(spell) {
+                        audio_player.play(audio.click.clone());
+                    }
+                }
+                ShopButton::StartDay => {
+                    audio_player.play(audio.click.clone());
+                    state.set(GameState::ActiveGame).unwrap();
+                }
+            },
+            Interaction::Hovered => {
+                *color = BUTTON_HOVER.into();
+            }
+            Interaction::None => {
+                *color = BUTTON_NORMAL.into();
+            }
+        }
+    }
+}
+
+/// Keeps the soul count, upgrade prices and learned spells in sync after every purchase.
+pub fn update_shop_text(
+    run_state: Res<RunState>,
+    q_buttons: Query<(&ShopButton, &Children)>,
+    mut q_text: Query<(&mut Text, Option<&Ui>)>,
+) {
+    if !run_state.is_changed() {
+        return;
+    }
+    for (mut text, ui) in q_text.iter_mut() {
+        if let Some(Ui::SoulsDisplay) = ui {
+            text.sections[0].value = format!("Souls: {}", run_state.souls);
+        }
+    }
+    for (button_type, children) in q_buttons.iter() {
+        let label = match button_type {
+            ShopButton::Buy(upgrade) => upgrade_label(&run_state, *upgrade),
+            ShopButton::Learn(spell) => spell_label(&run_state, *spell),
+            ShopButton::StartDay => continue,
+        };
+        for child in children.iter() {
+            if let Ok((mut text, _)) = q_text.get_mut(*child) {
+                text.sections[0].value = label.clone();
+            }
+        }
+    }
+}
+
+// Controls
+
+/// How many actions are listed down each column of the controls screen.
+const CONTROLS_PER_COLUMN: usize = 9;
+
+fn binding_label(
+    input_map: &InputMap,
+    screen: &ControlsScreen,
+    action: Action,
+    device: InputDevice,
+) -> String {
+    if screen.listening == Some((action, device)) {
+        "...".to_string()
+    } else {
+        input_map.binding_name(action, device)
+    }
+}
+
+pub fn spawn_controls(mut commands: Commands, fonts: Res<GameFonts>, input_map: Res<InputMap>) {
+    let screen = ControlsScreen::default();
+    let text_style = |font_size: f32, color: Color| TextStyle {
+        font: fonts.main.clone(),
+        font_size,
+        color,
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(Ui::Core)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Controls",
+                    text_style(64.0, Color::WHITE),
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+
+            let spawn_binding = |parent: &mut ChildBuilder, action: Action, device: InputDevice| {
+                let style = Style {
+                    size: Size::new(Val::Px(96.0), Val::Px(28.0)),
+                    margin: Rect::all(Val::Px(2.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                };
+                if device == InputDevice::Gamepad && !action.gamepad_bindable() {
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style,
+                            color: Color::NONE.into(),
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn_bundle(TextBundle {
+                                text: Text::with_section(
+                                    "LS",
+                                    text_style(16.0, TEXT_COLOR),
+                                    Default::default(),
+                                ),
+                                ..Default::default()
+                            });
+                        });
+                    return;
+                }
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style,
+                        color: BUTTON_NORMAL.into(),
+                        ..Default::default()
+                    })
+                    .insert(ControlsButton::Rebind(action, device))
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                binding_label(&input_map, &screen, action, device),
+                                text_style(16.0, TEXT_COLOR),
+                                Default::default(),
+                            ),
+                            ..Default::default()
+                        });
+                    });
+            };
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(16.0)),
+                        ..Default::default()
+                    },
+                    color: Color::NONE.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for column in Action::ALL.chunks(CONTROLS_PER_COLUMN) {
+                        parent
+                            .spawn_bundle(NodeBundle {
+                                style: Style {
+                                    flex_direction: FlexDirection::ColumnReverse,
+                                    margin: Rect {
+                                        left: Val::Px(16.0),
+                                        right: Val::Px(16.0),
+                                        ..Default::default()
+                                    },
+                                    ..Default::default()
+                                },
+                                color: Color::NONE.into(),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                for action in column {
+                                    parent
+                                        .spawn_bundle(NodeBundle {
+                                            style: Style {
+                                                align_items: AlignItems::Center,
+                                                ..Default::default()
+                                            },
+                                            color: Color::NONE.into(),
+                                            ..Default::default()
+                                        })
+                                        .with_children(|parent| {
+                                            parent.spawn_bundle(TextBundle {
+                                                style: Style {
+                                                    size: Size::new(Val::Px(136.0), Val::Px(20.0)),
+                                                    ..Default::default()
+                                                },
+                                                text: Text::with_section(
+                                                    action.name(),
+                                                    text_style(20.0, Color::WHITE),
+                                                    Default::default(),
+                                                ),
+                                                ..Default::default()
+                                            });
+                                            spawn_binding(
+                                                parent,
+                                                *action,
+                                                InputDevice::KeyboardMouse,
+                                            );
+                                            spawn_binding(parent, *action, InputDevice::Gamepad);
+                                        });
+                                }
+                            });
+                    }
+                });
+
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        String::new(),
+                        text_style(24.0, TEXT_COLOR),
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(Ui::ControlsStatus);
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    color: Color::NONE.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for (text, context) in [
+                        ("Reset", ControlsButton::ResetDefaults),
+                        ("Back", ControlsButton::Back),
+                    ] {
+                        let mut button = parent.spawn_bundle(ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+                                margin: Rect {
+                                    top: Val::Px(30.0),
+                                    left: Val::Px(30.0),
+                                    right: Val::Px(30.0),
+                                    ..Default::default()
+                                },
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            color: BUTTON_NORMAL.into(),
+                            ..Default::default()
+                        });
+                        if let ControlsButton::Back = context {
+                            button.insert(BackButton);
+                        }
+                        button.insert(context).with_children(|parent| {
+                            parent.spawn_bundle(TextBundle {
+                                text: Text::with_section(
+                                    text,
+                                    text_style(32.0, Color::WHITE),
+                                    Default::default(),
+                                ),
+                                ..Default::default()
+                            });
+                        });
+                    }
+                });
+        });
+
+    commands.insert_resource(screen);
+}
+
+/// Picks a binding to change, then takes the next key or button pressed as its replacement.
+/// A binding already used by another action is swapped over to it, so nothing is ever doubled up.
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+pub fn button_controls(
+    mut q_interaction: Query<
+        (&Interaction, &mut UiColor, &ControlsButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut state: ResMut<State<GameState>>,
+    mut input_map: ResMut<InputMap>,
+    mut screen: ResMut<ControlsScreen>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    audio: Res<GameAudio>,
+    audio_player: Res<Audio>,
+) {
+    // Whatever is pressed while listening is the new binding, clicks included
+    let listening = screen.listening;
+    if let Some((action, device)) = listening {
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            screen.listening = None;
+            screen.message = String::new();
+        } else {
+            let conflict = match device {
+                InputDevice::KeyboardMouse => keyboard_input
+                    .get_just_pressed()
+                    .next()
+                    .map(|key| InputBinding::Key(*key))
+                    .or_else(|| {
+                        mouse_input
+                            .get_just_pressed()
+                            .next()
+                            .map(|button| InputBinding::Mouse(*button))
+                    })
+                    .map(|binding| input_map.rebind_keyboard(action, binding)),
+                InputDevice::Gamepad => gamepad_buttons
+                    .get_just_pressed()
+                    .next()
+                    .map(|GamepadButton(_, button)| input_map.rebind_gamepad(action, *button)),
+            };
+            if let Some(conflict) = conflict {
+                audio_player.play(audio.click.clone());
+                screen.listening = None;
+                screen.message = match conflict {
+                    Some(other) if input_map.binding_name(other, device) == "-" => {
+                        format!("{} is now unbound", other.name())
+                    }
+                    Some(other) => {
+                        format!("{} and {} swapped bindings", action.name(), other.name())
+                    }
+                    None => String::new(),
+                };
+            }
+        }
+    }
+
+    for (interaction, mut color, button_type) in q_interaction.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                if listening.is_some() {
+                    continue;
+                }
+                audio_player.play(audio.click.clone());
+                match *button_type {
+                    ControlsButton::Rebind(action, device) => {
+                        screen.listening = Some((action, device));
+                        screen.message = match device {
+                            InputDevice::KeyboardMouse => {
+                                format!(
+                                    "Press a key or mouse button for {} (Esc to cancel)",
+                                    action.name()
+                                )
+                            }
+                            InputDevice::Gamepad => {
+                                format!(
+                                    "Press a gamepad button for {} (Esc to cancel)",
+                                    action.name()
+                                )
+                            }
+                        };
+                    }
+                    ControlsButton::ResetDefaults => {
+                        *input_map = InputMap::default();
+                        screen.message = "Controls reset to defaults".to_string();
+                    }
+                    ControlsButton::Back => {
+                        state.set(GameState::MainMenu).unwrap();
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *color = BUTTON_HOVER.into();
+            }
+            Interaction::None => {
+                *color = BUTTON_NORMAL.into();
+            }
+        }
+    }
+}
+
+/// Keeps the binding buttons and status line in step with the bindings.
+pub fn update_controls_text(
+    input_map: Res<InputMap>,
+    screen: Res<ControlsScreen>,
+    q_buttons: Query<(&ControlsButton, &Children)>,
+    mut q_text: Query<(&mut Text, Option<&Ui>)>,
+) {
+    if !input_map.is_changed() && !screen.is_changed() {
+        return;
+    }
+    for (mut text, ui) in q_text.iter_mut() {
+        if let Some(Ui::ControlsStatus) = ui {
+            text.sections[0].value = screen.message.clone();
+        }
+    }
+    for (button_type, children) in q_buttons.iter() {
+        if let ControlsButton::Rebind(action, device) = *button_type {
+            let label = binding_label(&input_map, &screen, action, device);
+            for child in children.iter() {
+                if let Ok((mut text, _)) = q_text.get_mut(*child) {
+                    text.sections[0].value = label.clone();
+                }
+            }
+        }
+    }
+}
+
+// Pause
+
+/// Pauses the day on Escape or Start, or whenever the window loses focus.
+pub fn pause_game(
+    mut state: ResMut<State<GameState>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    active_gamepad: Res<ActiveGamepad>,
+    mut focus_events: EventReader<WindowFocused>,
+) {
+    let lost_focus = focus_events.iter().any(|event| !event.focused);
+    if keyboard_input.just_pressed(KeyCode::Escape)
+        || active_gamepad.just_pressed(&gamepad_buttons, GamepadButtonType::Start)
+        || lost_focus
+    {
+        // Runs after everything that ends the day, which may already have queued its transition
+        let _ = state.push(GameState::Paused);
+    }
+}
+
+pub fn spawn_pause_menu(
+    mut commands: Commands,
+    fonts: Res<GameFonts>,
+    mut physics_time: ResMut<PhysicsTime>,
+) {
+    physics_time.pause();
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            ..Default::default()
+        })
+        .insert(Ui::Core)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Paused",
+                    TextStyle {
+                        font: fonts.main.clone(),
+                        font_size: 64.0,
+                        color: Color::WHITE,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+
+            for (text, context) in [
+                ("Resume", PauseButton::Resume),
+                ("Settings", PauseButton::Settings),
+                ("Restart Day", PauseButton::RestartDay),
+                ("Quit to Menu", PauseButton::QuitToMenu),
+            ] {
+                let mut button = parent.spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+                        margin: Rect {
+                            top: Val::Px(30.0),
+                            ..Default::default()
+                        },
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    color: BUTTON_NORMAL.into(),
+                    ..Default::default()
+                });
+                if let PauseButton::Resume = context {
+                    button.insert(BackButton);
+                }
+                button.insert(context).with_children(|parent| {
+                    parent.spawn_bundle(TextBundle {
+                        text: Text::with_section(
+                            text,
+                            TextStyle {
+                                font: fonts.main.clone(),
+                                font_size: 32.0,
+                                color: Color::WHITE,
+                            },
+                            Default::default(),
+                        ),
+                        ..Default::default()
+                    });
+                });
+            }
+        });
+}
+
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+pub fn button_pause(
+    mut q_interaction: Query<
+        (&Interaction, &mut UiColor, &PauseButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut state: ResMut<State<GameState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut morale: ResMut<EnemyMorale>,
+    mut current_day: ResMut<CurrentDay>,
+    audio: Res<GameAudio>,
+    audio_player: Res<Audio>,
+) {
+    for (interaction, mut color, button_type) in q_interaction.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                audio_player.play(audio.click.clone());
+                match *button_type {
+                    PauseButton::Resume => {
+                        // Otherwise the same Escape press would pause the game again straight away
+                        keyboard_input.reset(KeyCode::Escape);
+                        state.pop().unwrap();
+                    }
+                    PauseButton::Settings => {
+                        state.push(GameState::Settings).unwrap();
+                    }
+                    PauseButton::RestartDay => {
+                        // Throw away everything the day has counted so far
+                        morale.change = 0.0;
+                        morale.enemies_killed = 0;
+                        morale.minion_kills = 0;
+                        current_day.player_damaged = 0.0;
+                        current_day.deaths = 0;
+                        // Replacing leaves the active game too, so it's cleaned up and set up again
+                        state.replace(GameState::ActiveGame).unwrap();
+                    }
+                    PauseButton::QuitToMenu => {
+                        state.replace(GameState::MainMenu).unwrap();
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *color = BUTTON_HOVER.into();
+            }
+            Interaction::None => {
+                *color = BUTTON_NORMAL.into();
+            }
+        }
+    }
+}
+
+pub fn resume_physics(mut physics_time: ResMut<PhysicsTime>) {
+    physics_time.resume();
+}
+
+// Settings
+
+pub fn spawn_settings(mut commands: Commands, fonts: Res<GameFonts>, settings: Res<Settings>) {
+    let text_style = |font_size: f32| TextStyle {
+        font: fonts.main.clone(),
+        font_size,
+        color: Color::WHITE,
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            // Opened from the pause menu, the paused day is still showing underneath
+            color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            ..Default::default()
+        })
+        .insert(Ui::Core)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect {
+                        bottom: Val::Px(16.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section("Settings", text_style(64.0), Default::default()),
+                ..Default::default()
+            });
+
+            for setting in Setting::ALL {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        color: Color::NONE.into(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn_bundle(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(300.0), Val::Px(40.0)),
+                                    align_items: AlignItems::Center,
+                                    ..Default::default()
+                                },
+                                color: Color::NONE.into(),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn_bundle(TextBundle {
+                                    text: Text::with_section(
+                                        setting.name(),
+                                        text_style(28.0),
+                                        Default::default(),
+                                    ),
+                                    ..Default::default()
+                                });
+                            });
+
+                        parent
+                            .spawn_bundle(ButtonBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(160.0), Val::Px(36.0)),
+                                    margin: Rect::all(Val::Px(2.0)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..Default::default()
+                                },
+                                color: BUTTON_NORMAL.into(),
+                                ..Default::default()
+                            })
+                            .insert(SettingsButton::Cycle(setting))
+                            .with_children(|parent| {
+                                parent.spawn_bundle(TextBundle {
+                                    text: Text::with_section(
+                                        settings.value_label(setting),
+                                        text_style(24.0),
+                                        Default::default(),
+                                    ),
+                                    ..Default::default()
+                                });
+                            });
+                    });
+            }
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+                        margin: Rect {
+                            top: Val::Px(24.0),
+                            ..Default::default()
+                        },
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    color: BUTTON_NORMAL.into(),
+                    ..Default::default()
+                })
+                .insert(SettingsButton::Back)
+                .insert(BackButton)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle {
+                        text: Text::with_section("Back", text_style(32.0), Default::default()),
+                        ..Default::default()
+                    });
+                });
+        });
+}
+
+/// Steps each setting on to its next option. Changes are applied and saved as soon as they're made.
+#[allow(clippy::type_complexity)]
+pub fn button_settings(
+    mut q_interaction: Query<
+        (&Interaction, &mut UiColor, &SettingsButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut state: ResMut<State<GameState>>,
+    mut settings: ResMut<Settings>,
+    audio: Res<GameAudio>,
+    audio_player: Res<Audio>,
+) {
+    for (interaction, mut color, button_type) in q_interaction.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                audio_player.play(audio.click.clone());
+                match *button_type {
+                    SettingsButton::Cycle(setting) => settings.cycle(setting),
+                    // Pushed over the pause menu, or set from the main menu
+                    SettingsButton::Back if state.inactives().is_empty() => {
+                        state.set(GameState::MainMenu).unwrap();
+                    }
+                    SettingsButton::Back => {
+                        state.pop().unwrap();
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *color = BUTTON_HOVER.into();
+            }
+            Interaction::None => {
+                *color = BUTTON_NORMAL.into();
+            }
+        }
+    }
+}
+
+/// Keeps the setting buttons showing their current values.
+pub fn update_settings_text(
+    settings: Res<Settings>,
+    q_buttons: Query<(&SettingsButton, &Children)>,
+    mut q_text: Query<&mut Text>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (button_type, children) in q_buttons.iter() {
+        if let SettingsButton::Cycle(setting) = *button_type {
+            for child in children.iter() {
+                if let Ok(mut text) = q_text.get_mut(*child) {
+                    text.sections[0].value = settings.value_label(setting);
+                }
+            }
+        }
+    }
+}
+
+// Credits
+
+#[allow(clippy::type_complexity)]
+pub fn button_credits_back(
+    mut q_interaction: Query<(&Interaction, &mut UiColor), (Changed<Interaction>, With<Button>)>,
+    mut state: ResMut<State<GameState>>,
+    audio: Res<GameAudio>,
+    audio_player: Res<Audio>,
+) {
+    for (interaction, mut color) in q_interaction.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                audio_player.play(audio.click.clone());
+                state.set(GameState::MainMenu).unwrap();
+            }
+            Interaction::Hovered => {
+                *color = BUTTON_HOVER.into();
+            }
+            Interaction::None => {
+                *color = BUTTON_NORMAL.into();
+            }
+        }
+    }
+}
+
+pub fn spawn_credits(mut commands: Commands, fonts: Res<GameFonts>, sprites: Res<GameSprites>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::SpaceBetween,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(Ui::Core)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text {
+                        sections: vec![
+                            TextSection {
+                                value: "Credits\n".to_string(),
+                                style: TextStyle {
+                                    font: fonts.main.clone(),
+                                    font_size: 64.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "\nAssets:\nFont: ".to_string(),
+                                style: TextStyle {
+                                    font: fonts.main.clone(),
+                                    font_size: 32.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "m5x7 by Daniel Linssen\nhttps://managore.itch.io/m5x7".to_string(),
+                                style: TextStyle {
+                                    font: fonts.main.clone(),
+                                    font_size: 32.0,
+                                    color: TEXT_COLOR,
+                                },
+                            },
+                            TextSection {
+                                value: "\nEnemy and Player Sprites: ".to_string(),
+                                style: TextStyle {
+                                    font: fonts.main.clone(),
+                                    font_size: 32.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "16x16 DungeonTileset II by 0x72\nhttps://0x72.itch.io/dungeontileset-ii\n".to_string(),
+                                style: TextStyle {
+                                    font: fonts.main.clone(),
+                                    font_size: 32.0,
+                                    color: TEXT_COLOR,
+                                },
+                            },
+                            TextSection {
+                                value: "\n\nGame made by ".to_string(),
+                                style: TextStyle {
+                                    font: fonts.main.clone(),
+                                    font_size: 32.0,
+                                    color: TEXT_COLOR,
+                                },
+                            },
+                            TextSection {
+                                value: "ProspectPyxis".to_string(),
+                                style: TextStyle {
+                                    font: fonts.main.clone(),
+                                    font_size: 32.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                        ],
+                        alignment: TextAlignment {
+                            horizontal: HorizontalAlign::Center,
+                            vertical: VerticalAlign::Center,
+                        },
+                    },
+                    ..Default::default()
+                })
+                .insert(Ui::NarrationText);
+
+            parent.spawn_bundle(ImageBundle {
+                image: UiImage(sprites.bevy.clone()),
+                transform: Transform::from_scale(Vec3::new(0.5, 0.5, 0.0)),
+                style: Style {
+                    margin: Rect {
+                        top: Val::Px(30.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+                        margin: Rect {
+                            top: Val::Px(30.0),
+                            bottom: Val::Px(30.0),
+                            ..Default::default()
+                        },
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    color: BUTTON_NORMAL.into(),
+                    ..Default::default()
+                })
+                .insert(BackButton)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle {
+                        text: Text::with_section(
+                            "Back",
+                            TextStyle {
+                                font: fonts.main.clone(),
+                                font_size: 32.0,
+                                color: Color::WHITE,
+                            },
+                            Default::default(),
+                        ),
+                        ..Default::default()
+                    });
+                });
+        });
+}
+
+// Navigation
+
+/// Picks the button a focus move lands on: the nearest one in that direction,
+/// favouring buttons straight ahead over ones off to the side.
+fn next_focus(
+    from: Vec2,
+    direction: Vec2,
+    buttons: impl Iterator<Item = (Entity, Vec2)>,
+) -> Option<Entity> {
+    buttons
+        .filter_map(|(ent, pos)| {
+            let offset = pos - from;
+            let ahead = offset.dot(direction);
+            let aside = offset.perp_dot(direction).abs();
+            (ahead > 1.0).then(|| (ent, ahead + aside * 2.0))
+        })
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+        .map(|(ent, _)| ent)
+}
+
+/// Moves focus between buttons with the arrow keys or D-pad and presses the focused one
+/// with Enter or A, by setting its `Interaction` just like a click would. Escape or B
+/// presses the menu's back button. Any mouse movement hands control back to the mouse.
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+pub fn navigate_menu(
+    mut focus: ResMut<MenuFocus>,
+    mut pressed: Local<Option<Entity>>,
+    mut q_buttons: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &mut Interaction,
+            &mut UiColor,
+            Option<&BackButton>,
+        ),
+        With<Button>,
+    >,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    active_gamepad: Res<ActiveGamepad>,
+    mut cursor_moved: EventReader<CursorMoved>,
+    controls_screen: Option<Res<ControlsScreen>>,
+) {
+    // A press only lasts a frame, as if the mouse had clicked and let go straight away
+    if let Some(ent) = pressed.take() {
+        if let Ok((_, _, mut interaction, _, _)) = q_buttons.get_mut(ent) {
+            if *interaction == Interaction::Clicked {
+                *interaction = Interaction::None;
+            }
+        }
+    }
+    // Focus goes away with the menu it was in
+    if focus.0.map_or(false, |ent| q_buttons.get(ent).is_err()) {
+        focus.0 = None;
+    }
+    if cursor_moved.iter().count() > 0 {
+        if let Some((_, _, interaction, mut color, _)) =
+            focus.0.take().and_then(|ent| q_buttons.get_mut(ent).ok())
+        {
+            if *interaction == Interaction::None {
+                *color = BUTTON_NORMAL.into();
+            }
+        }
+        return;
+    }
+    // The controls screen wants these keys as new bindings
+    if controls_screen.map_or(false, |screen| screen.listening.is_some()) {
+        return;
+    }
+
+    let just_pressed = |key: KeyCode, button: GamepadButtonType| {
+        keyboard_input.just_pressed(key) || active_gamepad.just_pressed(&gamepad_buttons, button)
+    };
+    let direction = if just_pressed(KeyCode::Up, GamepadButtonType::DPadUp) {
+        Some(Vec2::Y)
+    } else if just_pressed(KeyCode::Down, GamepadButtonType::DPadDown) {
+        Some(-Vec2::Y)
+    } else if just_pressed(KeyCode::Left, GamepadButtonType::DPadLeft) {
+        Some(-Vec2::X)
+    } else if just_pressed(KeyCode::Right, GamepadButtonType::DPadRight) {
+        Some(Vec2::X)
+    } else {
+        None
+    };
+    let activate = just_pressed(KeyCode::Return, GamepadButtonType::South);
+    let back = just_pressed(KeyCode::Escape, GamepadButtonType::East);
+
+    let previous = focus.0;
+    if direction.is_some() || activate {
+        let positions = q_buttons
+            .iter()
+            .map(|(ent, transform, ..)| (ent, transform.translation.truncate()));
+        focus.0 = match (focus.0, direction) {
+            (Some(current), Some(direction)) => {
+                let from = q_buttons.get(current).unwrap().1.translation.truncate();
+                next_focus(from, direction, positions).or(Some(current))
+            }
+            (Some(current), None) => Some(current),
+            // Nothing has focus yet, so start from the top left button
+            (None, _) => positions
+                .max_by(|(_, a), (_, b)| {
+                    a.y.partial_cmp(&b.y)
+                        .unwrap()
+                        .then(b.x.partial_cmp(&a.x).unwrap())
+                })
+                .map(|(ent, _)| ent),
+        };
+    }
+
+    let target = if activate && previous.is_some() {
+        focus.0
+    } else if back {
+        q_buttons
+            .iter()
+            .find(|(.., back_button)| back_button.is_some())
+            .map(|(ent, ..)| ent)
+    } else {
+        None
+    };
+    if let Some((ent, _, mut interaction, _, _)) =
+        target.and_then(|ent| q_buttons.get_mut(ent).ok())
+    {
+        *interaction = Interaction::Clicked;
+        *pressed = Some(ent);
+    }
+
+    if focus.0 != previous {
+        if let Some((_, _, interaction, mut color, _)) =
+            previous.and_then(|ent| q_buttons.get_mut(ent).ok())
+        {
+            if *interaction == Interaction::None {
+                *color = BUTTON_NORMAL.into();
+            }
+        }
+    }
+    // Button systems reset colors as the mouse moves on and off, so keep reapplying the highlight
+    if let Some((_, _, _, mut color, _)) = focus.0.and_then(|ent| q_buttons.get_mut(ent).ok()) {
+        if color.0 != BUTTON_HOVER {
+            *color = BUTTON_HOVER.into();
+        }
+    }
+}
+
+/// Despawns a menu drawn over the game, leaving the arena underneath alone.
+pub fn despawn_menu_ui(mut commands: Commands, q_ui: Query<(Entity, &Ui), With<Children>>) {
+    for (ent, ui) in q_ui.iter() {
+        if let Ui::Core = ui {
+            commands.entity(ent).despawn_recursive();
+        }
+    }
+}
+
 pub fn despawn_menu(
     mut commands: Commands,
     q_ui: Query<(Entity, &Ui), With<Children>>,
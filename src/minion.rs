@@ -0,0 +1,278 @@
+use crate::common::{
+    Action, DamageEnemyEvent, DamageSource, DamageType, DamagesPlayer, Enemy, EnemyAI,
+    EnemyAttackHitEvent, EnemyContactEvent, EnemyProjectile, GameFonts, GamePhysicsLayer,
+    GameSprites, Health, HitTarget, InGameUI, Minion, MinionCommand, MinionCommandEvent,
+    MinionKind, MinionOrders, Phylactery, Player, Resistances, Ui,
+};
+use bevy::prelude::*;
+use heron::prelude::*;
+
+pub fn spawn_minion(
+    commands: &mut Commands,
+    sprites: &GameSprites,
+    kind: MinionKind,
+    position: Vec3,
+) {
+    // Ghouls are quicker and hit harder, but fall apart more easily
+    let (texture, color, speed, damage, attack_speed, health) = match kind {
+        MinionKind::Skeleton => (
+            sprites.soldier.clone(),
+            Color::rgb(0.5, 1.0, 0.6),
+            140.0,
+            1.0,
+            0.8,
+            3.0,
+        ),
+        MinionKind::Ghoul => (
+            sprites.archer.clone(),
+            Color::rgb(0.6, 0.45, 0.8),
+            180.0,
+            1.5,
+            0.6,
+            2.0,
+        ),
+    };
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture,
+            sprite: Sprite {
+                color,
+                ..Default::default()
+            },
+            transform: Transform {
+                translation: position,
+                scale: Vec3::new(1.5, 1.5, 0.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(kind.resistances())
+        .insert(Minion {
+            speed,
+            damage,
+            attack: Timer::from_seconds(attack_speed, true),
+            hold_position: position.truncate(),
+            contacts: Vec::new(),
+            contact_tick: Timer::from_seconds(1.5, true),
+        })
+        .insert(RigidBody::KinematicVelocityBased)
+        .insert(CollisionShape::Sphere { radius: 10.0 })
+        .insert(Velocity::from_linear(Vec3::ZERO))
+        .insert(
+            CollisionLayers::none()
+                .with_group(GamePhysicsLayer::Minion)
+                .with_masks(&[GamePhysicsLayer::Enemy, GamePhysicsLayer::EnemyAttack]),
+        )
+        .insert(Health::full(health));
+}
+
+pub fn spawn_minion_orders_ui(
+    mut commands: Commands,
+    fonts: Res<GameFonts>,
+    orders: Res<MinionOrders>,
+) {
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text::with_section(
+                format!("Minions: {}", orders.command.name()),
+                TextStyle {
+                    font: fonts.main.clone(),
+                    font_size: 32.0,
+                    color: Color::WHITE,
+                },
+                Default::default(),
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(5.0),
+                    left: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Ui::MinionOrders)
+        .insert(InGameUI);
+}
+
+pub fn issue_minion_commands(
+    actions: Res<Input<Action>>,
+    mut orders: ResMut<MinionOrders>,
+    mut q_minions: Query<(&mut Minion, &Transform)>,
+    mut command_writer: EventWriter<MinionCommandEvent>,
+) {
+    let command = match Action::MINION_COMMANDS
+        .iter()
+        .find(|action| actions.just_pressed(**action))
+        .and_then(Action::minion_command)
+    {
+        Some(command) => command,
+        None => return,
+    };
+
+    if command == MinionCommand::HoldPosition {
+        for (mut minion, transform) in q_minions.iter_mut() {
+            minion.hold_position = transform.translation.truncate();
+        }
+    }
+    orders.command = command;
+    command_writer.send(MinionCommandEvent(command));
+}
+
+pub fn update_minion_orders_ui(
+    mut q_text_ui: Query<(&Ui, &mut Text)>,
+    mut command_reader: EventReader<MinionCommandEvent>,
+) {
+    if let Some(MinionCommandEvent(command)) = command_reader.iter().last() {
+        for (ui, mut text) in q_text_ui.iter_mut() {
+            if let Ui::MinionOrders = ui {
+                text.sections[0].value = format!("Minions: {}", command.name());
+            }
+        }
+    }
+}
+
+/// Minions go after soldiers that are still fighting within reach of their current orders,
+/// and return to wherever those orders place them otherwise.
+pub fn update_minions(
+    mut q_minions: Query<(&mut Minion, &Transform, &mut Velocity, &mut Sprite)>,
+    q_enemies: Query<(Entity, &Enemy, &Transform, &Health)>,
+    mut damage_writer: EventWriter<DamageEnemyEvent>,
+    q_player: Query<&Transform, With<Player>>,
+    q_phylactery: Query<&Transform, With<Phylactery>>,
+    orders: Res<MinionOrders>,
+    time: Res<Time>,
+) {
+    let player = q_player.iter().next().map(|p| p.translation.truncate());
+    let phylactery = q_phylactery.iter().next().map(|p| p.translation.truncate());
+
+    for (mut minion, transform, mut velocity, mut sprite) in q_minions.iter_mut() {
+        let current_pos = transform.translation.truncate();
+        let (anchor, reach) = match orders.command {
+            MinionCommand::Follow => (player, 160.0),
+            MinionCommand::HoldPosition => (Some(minion.hold_position), 100.0),
+            MinionCommand::AttackNearestWave => (None, f32::INFINITY),
+            MinionCommand::GuardPhylactery => (phylactery, 200.0),
+        };
+        let reach_center = anchor.unwrap_or(current_pos);
+
+        let target = q_enemies
+            .iter()
+            .filter(|(_, enemy, enemy_t, health)| {
+                health.current > 0.0
+                    && !matches!(enemy.ai, EnemyAI::Afraid { speed: _ })
+                    && enemy_t.translation.truncate().distance(reach_center) <= reach
+            })
+            .min_by(|(_, _, a, _), (_, _, b, _)| {
+                current_pos
+                    .distance(a.translation.truncate())
+                    .partial_cmp(&current_pos.distance(b.translation.truncate()))
+                    .unwrap()
+            });
+
+        let destination = if let Some((ent, _, enemy_t, _)) = target {
+            let enemy_pos = enemy_t.translation.truncate();
+            if current_pos.distance(enemy_pos) <= 24.0 {
+                if minion.attack.tick(time.delta()).just_finished() {
+                    damage_writer.send(DamageEnemyEvent {
+                        enemy: ent,
+                        damage: minion.damage,
+                        damage_type: DamageType::Physical,
+                        effect: None,
+                        source: DamageSource::Minion,
+                    });
+                }
+                None
+            } else {
+                Some(enemy_pos)
+            }
+        } else {
+            let slack = match orders.command {
+                MinionCommand::HoldPosition => 8.0,
+                _ => 80.0,
+            };
+            anchor.filter(|a| current_pos.distance(*a) > slack)
+        };
+
+        velocity.linear = match destination {
+            Some(pos) => (pos - current_pos).normalize_or_zero().extend(0.0) * minion.speed,
+            None => Vec3::ZERO,
+        };
+        if velocity.linear.x != 0.0 {
+            sprite.flip_x = velocity.linear.x < 0.0;
+        }
+    }
+}
+
+pub fn check_enemy_minion_collision(
+    mut contact_events: EventReader<EnemyContactEvent>,
+    mut q_minions: Query<(&mut Minion, &mut Health, &Resistances), Without<Enemy>>,
+    q_enemies: Query<&DamagesPlayer, With<Enemy>>,
+) {
+    for contact in contact_events
+        .iter()
+        .filter(|c| c.target_kind == HitTarget::Minion)
+    {
+        if let (Ok((mut minion, mut health, resistances)), Ok(damages)) = (
+            q_minions.get_mut(contact.target),
+            q_enemies.get(contact.enemy),
+        ) {
+            if contact.started {
+                health.current -= resistances.damage_taken(damages.damage, damages.damage_type);
+                minion.contacts.push(contact.enemy);
+            } else {
+                minion.contacts.retain(|e| *e != contact.enemy);
+            }
+        }
+    }
+}
+
+/// Knights keep hurting any minion they stay in contact with.
+pub fn enemy_damage_minions(
+    mut q_minions: Query<(&mut Minion, &mut Health, &Resistances), Without<Enemy>>,
+    q_enemies: Query<&DamagesPlayer, With<Enemy>>,
+    time: Res<Time>,
+) {
+    for (mut minion, mut health, resistances) in q_minions.iter_mut() {
+        minion.contacts.retain(|e| q_enemies.get(*e).is_ok());
+        if minion.contacts.is_empty() {
+            minion.contact_tick.reset();
+        } else if minion.contact_tick.tick(time.delta()).just_finished() {
+            health.current -= minion
+                .contacts
+                .iter()
+                .filter_map(|e| q_enemies.get(*e).ok())
+                .map(|damages| resistances.damage_taken(damages.damage, damages.damage_type))
+                .sum::<f32>();
+        }
+    }
+}
+
+pub fn enemy_projectile_damage_minion(
+    mut commands: Commands,
+    mut hit_events: EventReader<EnemyAttackHitEvent>,
+    mut q_minions: Query<(&mut Health, &Resistances), With<Minion>>,
+    q_projectiles: Query<&EnemyProjectile>,
+) {
+    for hit in hit_events
+        .iter()
+        .filter(|h| h.target_kind == HitTarget::Minion)
+    {
+        if let (Ok((mut health, resistances)), Ok(projectile)) =
+            (q_minions.get_mut(hit.target), q_projectiles.get(hit.attack))
+        {
+            health.current -= resistances.damage_taken(projectile.damage, projectile.damage_type);
+            commands.entity(hit.attack).despawn();
+        }
+    }
+}
+
+pub fn despawn_minions(mut commands: Commands, q_minions: Query<(Entity, &Health), With<Minion>>) {
+    for (ent, health) in q_minions.iter() {
+        if health.current <= 0.0 {
+            commands.entity(ent).despawn();
+        }
+    }
+}
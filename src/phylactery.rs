@@ -0,0 +1,144 @@
+use crate::{
+    arena::ARENA_TILE_SIZE,
+    common::{
+        ColorPalette, DamagesPlayer, DayEndReason, EndDayEvent, Enemy, EnemyContactEvent,
+        EnemyMorale, GamePhysicsLayer, GameState, Health, HitTarget, InGameUI, Phylactery, Ui,
+    },
+};
+use bevy::{math::const_vec3, prelude::*};
+use heron::prelude::*;
+
+/// Where the phylactery rests, a tile behind the spot the lich rises from.
+/// That spot is always in the middle of the clearing the arena is generated around.
+pub const PHYLACTERY_POSITION: Vec3 = const_vec3!([0.0, ARENA_TILE_SIZE, 0.4]);
+
+pub fn spawn_phylactery(mut commands: Commands) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.4, 0.9, 0.8),
+                custom_size: Some(Vec2::new(24.0, 24.0)),
+                ..Default::default()
+            },
+            transform: Transform {
+                translation: PHYLACTERY_POSITION,
+                rotation: Quat::from_rotation_z(std::f32::consts::FRAC_PI_4),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Phylactery {
+            contacts: Vec::new(),
+            contact_tick: Timer::from_seconds(1.5, true),
+        })
+        .insert(Health::full(30.0))
+        .insert(RigidBody::Sensor)
+        .insert(CollisionShape::Sphere { radius: 16.0 })
+        .insert(
+            CollisionLayers::none()
+                .with_group(GamePhysicsLayer::Phylactery)
+                .with_mask(GamePhysicsLayer::Enemy),
+        );
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.4, 0.9, 0.8),
+                custom_size: Some(Vec2::new(48.0, 6.0)),
+                ..Default::default()
+            },
+            transform: Transform {
+                translation: PHYLACTERY_POSITION + Vec3::new(0.0, 24.0, 14.6),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Ui::PhylacteryHealthBar)
+        .insert(InGameUI);
+}
+
+pub fn check_enemy_phylactery_collision(
+    mut contact_events: EventReader<EnemyContactEvent>,
+    mut q_phylactery: Query<(&mut Phylactery, &mut Health), Without<Enemy>>,
+    q_enemies: Query<&DamagesPlayer, With<Enemy>>,
+    mut morale: ResMut<EnemyMorale>,
+) {
+    for contact in contact_events
+        .iter()
+        .filter(|c| c.target_kind == HitTarget::Phylactery)
+    {
+        if let (Ok((mut phylactery, mut health)), Ok(damages)) = (
+            q_phylactery.get_mut(contact.target),
+            q_enemies.get(contact.enemy),
+        ) {
+            if contact.started {
+                health.current -= damages.damage;
+                // Striking at the lich's soul directly emboldens the raiders
+                morale.change += damages.damage * 0.1;
+                phylactery.contacts.push(contact.enemy);
+            } else {
+                phylactery.contacts.retain(|e| *e != contact.enemy);
+            }
+        }
+    }
+}
+
+/// Raiders keep chipping away at the phylactery for as long as they stand next to it.
+pub fn enemy_damage_phylactery(
+    mut q_phylactery: Query<(&mut Phylactery, &mut Health), Without<Enemy>>,
+    q_enemies: Query<&DamagesPlayer, With<Enemy>>,
+    mut morale: ResMut<EnemyMorale>,
+    time: Res<Time>,
+) {
+    for (mut phylactery, mut health) in q_phylactery.iter_mut() {
+        phylactery.contacts.retain(|e| q_enemies.get(*e).is_ok());
+        if phylactery.contacts.is_empty() {
+            phylactery.contact_tick.reset();
+        } else if phylactery.contact_tick.tick(time.delta()).just_finished() {
+            let damage = phylactery
+                .contacts
+                .iter()
+                .filter_map(|e| q_enemies.get(*e).ok())
+                .map(|damages| damages.damage)
+                .sum::<f32>();
+            health.current -= damage;
+            morale.change += damage * 0.1;
+        }
+    }
+}
+
+/// Losing the phylactery ends the run outright, no matter how the day was going.
+pub fn check_phylactery_destroyed(
+    q_phylactery: Query<&Health, With<Phylactery>>,
+    mut state: ResMut<State<GameState>>,
+    mut day_end_writer: EventWriter<EndDayEvent>,
+) {
+    if let Some(health) = q_phylactery.iter().next() {
+        // Another system may already have ended the day this frame
+        if health.current <= 0.0 && state.set(GameState::GameOver).is_ok() {
+            day_end_writer.send(EndDayEvent {
+                reason: DayEndReason::PhylacteryDestroyed,
+            });
+        }
+    }
+}
+
+pub fn update_phylactery_health_bar(
+    mut q_ui: Query<(&mut Sprite, &Ui), Without<Phylactery>>,
+    q_phylactery: Query<&Health, With<Phylactery>>,
+    palette: Res<ColorPalette>,
+) {
+    if let Some(health) = q_phylactery.iter().next() {
+        for (mut sprite, ui) in q_ui.iter_mut() {
+            if let Ui::PhylacteryHealthBar = ui {
+                let fraction = (health.current / health.maximum).max(0.0);
+                sprite.custom_size = Some(Vec2::new(fraction * 48.0, 6.0));
+                sprite.color = if fraction <= 0.25 {
+                    palette.health_low
+                } else {
+                    Color::rgb(0.4, 0.9, 0.8)
+                };
+            }
+        }
+    }
+}
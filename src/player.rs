@@ -1,15 +1,53 @@
-use crate::common::{
-    get_cursor_position, ChangeSpellEvent, CurrentDay, DamagePlayerEvent, DamagesEnemy,
-    DayEndReason, DespawnTimer, EndDayEvent, EnemyMorale, GameAudio, GameFonts, GamePhysicsLayer,
-    GameSprites, GameState, Health, InGameUI, InvisTimer, LightningStrikeBolt, MainCamera, Player,
-    PlayerSpell, PlayerSpellData, SpellCooldowns, Ui, Vec3Utils, SCREEN_HEIGHT, SCREEN_WIDTH,
+use crate::{
+    arena::ArenaLayout,
+    common::{
+        Action, ActiveGamepad, Animated, ChainLightning, ChangeSpellEvent, ColorPalette, Corpse,
+        CurrentDay, DamagePlayerEvent, DamageType, DamagesEnemy, DayEndReason, DespawnTimer,
+        EndDayEvent, Enemy, EnemyMorale, GameAudio, GameFonts, GamePhysicsLayer, GameRules,
+        GameSprites, GameState, Health, InGameUI, InputDevice, InputMap, InvisTimer, Invulnerable,
+        LightningStorm, LightningStrikeBolt, Mana, Phylactery, Player, PlayerAim, PlayerSpell,
+        PlayerSpellData, ProjectileModifiers, Reforming, RunState, SpellCooldowns,
+        StatusEffectKind, StatusEffects, Ui, Vec3Utils, SCREEN_HEIGHT, SPELL_SLOTS, WORLD_HEIGHT,
+        WORLD_WIDTH,
+    },
+    minion::spawn_minion,
+    projectile::{spawn_fireball, spawn_lightning_bolt},
 };
-use bevy::{input::keyboard::KeyCode, prelude::*};
+use bevy::prelude::*;
 use bevy_kira_audio::Audio;
 use heron::prelude::*;
 use std::f32::consts::PI;
 
-pub fn spawn_player(mut commands: Commands, sprites: Res<GameSprites>) {
+const BLINK_DISTANCE: f32 = 200.0;
+const BLINK_TRAIL_LENGTH: usize = 5;
+const BLINK_INVULNERABILITY: f32 = 0.4;
+const POST_HIT_INVULNERABILITY: f32 = 0.25;
+/// Seconds it takes to fully charge a spell.
+pub const MAX_CHARGE: f32 = 1.0;
+pub const FIREBALL_SPREAD: f32 = PI / 16.0;
+/// How many soldiers a fireball burns through before any pierce modifiers,
+/// so it still cuts into a packed rank the way it always has.
+const FIREBALL_PIERCE: u32 = 2;
+/// How wide a fear wave's hitbox is across its direction of travel, before charging.
+pub const FEAR_WAVE_WIDTH: f32 = 128.0;
+pub const CHAIN_LIGHTNING_RADIUS: f32 = 48.0;
+pub const LIGHTNING_STORM_RADIUS: f32 = 192.0;
+pub const RAISE_DEAD_RADIUS: f32 = 96.0;
+const REFORM_DELAY: f32 = 5.0;
+const REFORM_INVULNERABILITY: f32 = 1.5;
+/// How much humanity's morale rises each time the lich is struck down mid-day.
+/// Worth about as much as ten kills, so reforming is a setback but still far kinder
+/// than the day ending on a death.
+const DEATH_MORALE_BONUS: f32 = 0.5;
+
+/// What the lich collides with, whenever it isn't reforming.
+fn player_collision_layers() -> CollisionLayers {
+    CollisionLayers::none()
+        .with_group(GamePhysicsLayer::Player)
+        .with_masks(&[GamePhysicsLayer::Enemy, GamePhysicsLayer::EnemyAttack])
+}
+
+pub fn spawn_player(mut commands: Commands, sprites: Res<GameSprites>, run_state: Res<RunState>) {
     commands
         .spawn_bundle(SpriteBundle {
             texture: sprites.lich.clone(),
@@ -23,21 +61,19 @@ pub fn spawn_player(mut commands: Commands, sprites: Res<GameSprites>) {
         .insert(Player)
         .insert(RigidBody::KinematicPositionBased)
         .insert(CollisionShape::Sphere { radius: 24.0 })
-        .insert(
-            CollisionLayers::none()
-                .with_group(GamePhysicsLayer::Player)
-                .with_masks(&[GamePhysicsLayer::Enemy, GamePhysicsLayer::EnemyAttack]),
-        )
-        .insert(Health::full(200.0))
+        .insert(player_collision_layers())
+        .insert(Health::full(run_state.upgrades.max_health()))
+        .insert(Mana::full(run_state.upgrades.max_mana(), 5.0))
         .insert(PlayerSpellData {
             selected: PlayerSpell::Fireball,
-            cooldowns: SpellCooldowns::default(),
+            cooldowns: SpellCooldowns::scaled(run_state.upgrades.cooldown_multiplier()),
             no_shoot_delay: Timer::from_seconds(1.0, false),
             no_shoot_penalty: Timer::from_seconds(0.1, true),
+            charge: None,
         });
 }
 
-pub fn spawn_player_ui(mut commands: Commands, sprites: Res<GameSprites>) {
+pub fn spawn_player_ui(mut commands: Commands, sprites: Res<GameSprites>, fonts: Res<GameFonts>) {
     commands
         .spawn_bundle(SpriteBundle {
             sprite: Sprite {
@@ -54,6 +90,39 @@ pub fn spawn_player_ui(mut commands: Commands, sprites: Res<GameSprites>) {
         .insert(Ui::HealthBarMain)
         .insert(InGameUI);
 
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.3, 0.5, 1.0),
+                custom_size: Some(Vec2::new(100.0, 6.0)),
+                ..Default::default()
+            },
+            transform: Transform {
+                translation: Vec3::new(0.0, -70.0, 15.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Ui::ManaBar)
+        .insert(InGameUI);
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(1.0, 0.6, 0.2),
+                custom_size: Some(Vec2::new(0.0, 4.0)),
+                ..Default::default()
+            },
+            transform: Transform {
+                translation: Vec3::new(0.0, 40.0, 15.0),
+                ..Default::default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(Ui::ChargeMeter)
+        .insert(InGameUI);
+
     commands
         .spawn_bundle(SpriteBundle {
             texture: sprites.spell_icon_fireball.clone(),
@@ -68,174 +137,343 @@ pub fn spawn_player_ui(mut commands: Commands, sprites: Res<GameSprites>) {
         .insert(Ui::CurrentSpell)
         .insert(InGameUI)
         .insert(InvisTimer(Timer::from_seconds(1.0, false)));
+
+    commands
+        .spawn_bundle(Text2dBundle {
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: fonts.main.clone(),
+                    font_size: 24.0,
+                    color: Color::rgb(1.0, 0.8, 0.4),
+                },
+                TextAlignment {
+                    horizontal: HorizontalAlign::Center,
+                    vertical: VerticalAlign::Center,
+                },
+            ),
+            transform: Transform::from_xyz(0.0, 92.0, 15.0),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(Ui::SpellModifiers)
+        .insert(InGameUI)
+        .insert(InvisTimer(Timer::from_seconds(1.0, false)));
 }
 
+#[allow(clippy::type_complexity)]
 pub fn player_move(
-    mut q: Query<(&mut Transform, &mut Sprite), With<Player>>,
-    keyboard_input: Res<Input<KeyCode>>,
+    mut q: Query<(&mut Transform, &mut Sprite), (With<Player>, Without<Reforming>)>,
+    actions: Res<Input<Action>>,
+    active_gamepad: Res<ActiveGamepad>,
+    axes: Res<Axis<GamepadAxis>>,
+    arena: Res<ArenaLayout>,
 ) {
     if let Some((mut transform, mut sprite)) = q.iter_mut().next() {
-        if keyboard_input.pressed(KeyCode::A) {
-            transform.translation.x -= 4.0;
-            sprite.flip_x = true;
+        let mut movement = Vec2::ZERO;
+        if actions.pressed(Action::MoveLeft) {
+            movement.x -= 4.0;
+        }
+        if actions.pressed(Action::MoveRight) {
+            movement.x += 4.0;
+        }
+        if actions.pressed(Action::MoveUp) {
+            movement.y += 4.0;
+        }
+        if actions.pressed(Action::MoveDown) {
+            movement.y -= 4.0;
+        }
+        if movement == Vec2::ZERO {
+            movement = active_gamepad.stick(
+                &axes,
+                GamepadAxisType::LeftStickX,
+                GamepadAxisType::LeftStickY,
+            ) * 4.0;
         }
-        if keyboard_input.pressed(KeyCode::D) {
-            transform.translation.x += 4.0;
-            sprite.flip_x = false;
+        if movement.x != 0.0 {
+            sprite.flip_x = movement.x < 0.0;
         }
-        if keyboard_input.pressed(KeyCode::W) {
-            transform.translation.y += 4.0;
+
+        // Move each axis separately, so that the lich slides along obstacles
+        let current = transform.translation.truncate();
+        if arena.is_walkable_at(current + Vec2::new(movement.x, 0.0)) {
+            transform.translation.x += movement.x;
         }
-        if keyboard_input.pressed(KeyCode::S) {
-            transform.translation.y -= 4.0;
+        if arena.is_walkable_at(transform.translation.truncate() + Vec2::new(0.0, movement.y)) {
+            transform.translation.y += movement.y;
         }
+
         transform.translation.x = transform
             .translation
             .x
-            .clamp(-SCREEN_WIDTH / 2.0 + 32.0, SCREEN_WIDTH / 2.0 - 32.0);
+            .clamp(-WORLD_WIDTH / 2.0 + 32.0, WORLD_WIDTH / 2.0 - 32.0);
         transform.translation.y = transform
             .translation
             .y
-            .clamp(-SCREEN_HEIGHT / 2.0 + 40.0, SCREEN_HEIGHT / 2.0 + 40.0);
+            .clamp(-WORLD_HEIGHT / 2.0 + 40.0, WORLD_HEIGHT / 2.0 - 40.0);
+    }
+}
+
+/// Teleports the lich a short distance towards where it's aiming, briefly untouchable.
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+pub fn player_blink(
+    mut commands: Commands,
+    mut q_player: Query<
+        (Entity, &mut Transform, &Sprite, &mut PlayerSpellData),
+        (With<Player>, Without<Reforming>),
+    >,
+    actions: Res<Input<Action>>,
+    aim: Res<PlayerAim>,
+    arena: Res<ArenaLayout>,
+    sprites: Res<GameSprites>,
+    audio: Res<GameAudio>,
+    audio_player: Res<Audio>,
+) {
+    if !actions.just_pressed(Action::Blink) {
+        return;
+    }
+    if let Some((ent, mut transform, sprite, mut spell_data)) = q_player.iter_mut().next() {
+        if !spell_data.cooldowns.blink.finished() {
+            return;
+        }
+        let aim_pos = match aim.0 {
+            Some(aim_pos) => aim_pos,
+            None => return,
+        };
+        let start = transform.translation.truncate();
+        let offset = (aim_pos - start).clamp_length_max(BLINK_DISTANCE);
+        let bounds = Vec2::new(WORLD_WIDTH / 2.0 - 32.0, WORLD_HEIGHT / 2.0 - 40.0);
+
+        // Back off from the target until there's solid ground to land on
+        let end = (0..=8)
+            .map(|i| (start + offset * (1.0 - i as f32 / 8.0)).clamp(-bounds, bounds))
+            .find(|pos| arena.is_walkable_at(*pos))
+            .unwrap_or(start);
+        if end == start {
+            return;
+        }
+
+        for i in 0..BLINK_TRAIL_LENGTH {
+            let t = i as f32 / BLINK_TRAIL_LENGTH as f32;
+            commands
+                .spawn_bundle(SpriteBundle {
+                    texture: sprites.lich.clone(),
+                    sprite: Sprite {
+                        color: Color::rgba(0.2, 0.0, 0.3, 0.2 + t * 0.4),
+                        flip_x: sprite.flip_x,
+                        ..Default::default()
+                    },
+                    transform: Transform {
+                        translation: start.lerp(end, t).extend(0.45),
+                        scale: transform.scale,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(DespawnTimer(Timer::from_seconds(0.15 + t * 0.2, false)));
+        }
+
+        transform.translation = end.extend(transform.translation.z);
+        commands
+            .entity(ent)
+            .insert(Invulnerable(Timer::from_seconds(
+                BLINK_INVULNERABILITY,
+                false,
+            )));
+        audio_player.play(audio.fear_wave.clone());
+        spell_data.cooldowns.blink.reset();
+    }
+}
+
+/// Counts down invulnerability, and makes the lich translucent while it lasts.
+pub fn tick_invulnerability(
+    mut commands: Commands,
+    mut q_player: Query<(Entity, &mut Invulnerable, &mut Sprite), With<Player>>,
+    time: Res<Time>,
+) {
+    for (ent, mut invulnerable, mut sprite) in q_player.iter_mut() {
+        if invulnerable.0.tick(time.delta()).finished() {
+            sprite.color.set_a(1.0);
+            commands.entity(ent).remove::<Invulnerable>();
+        } else {
+            sprite.color.set_a(0.5);
+        }
     }
 }
 
 #[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
 pub fn player_shoot(
     mut commands: Commands,
     sprites: Res<GameSprites>,
     audio: Res<GameAudio>,
-    mut q_player: Query<(&Transform, &mut PlayerSpellData), With<Player>>,
-    wnds: Res<Windows>,
-    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
-    mouse_input: Res<Input<MouseButton>>,
+    mut q_player: Query<
+        (&Transform, &mut PlayerSpellData, &mut Mana),
+        (With<Player>, Without<Reforming>),
+    >,
+    aim: Res<PlayerAim>,
+    actions: Res<Input<Action>>,
     audio_player: Res<Audio>,
     time: Res<Time>,
     mut morale: ResMut<EnemyMorale>,
+    q_corpses: Query<(Entity, &Transform, &Corpse)>,
+    mut q_enemies: Query<(&Transform, &mut StatusEffects), With<Enemy>>,
+    run_state: Res<RunState>,
 ) {
-    if let Some((player_t, mut spell_data)) = q_player.iter_mut().next() {
-        if mouse_input.pressed(MouseButton::Left) {
-            if let Some(cursor_pos) = get_cursor_position(wnds, q_camera) {
+    if let Some((player_t, mut spell_data, mut mana)) = q_player.iter_mut().next() {
+        if actions.pressed(Action::Cast) {
+            if let Some(aim_pos) = aim.0 {
+                let mana_cost = spell_data.selected.mana_cost();
                 match spell_data.selected {
-                    PlayerSpell::Fireball => {
-                        if spell_data.cooldowns.fireball.finished() {
-                            for i in -1..=1 {
-                                commands
-                                    .spawn_bundle(SpriteBundle {
-                                        texture: sprites.fireball.clone(),
-                                        transform: Transform {
-                                            translation: player_t.translation,
-                                            scale: Vec3::new(2.0, 2.0, 0.0),
-                                            ..Default::default()
-                                        },
-                                        ..Default::default()
-                                    })
-                                    .insert(RigidBody::KinematicVelocityBased)
-                                    .insert(Velocity::from_linear(
-                                        (cursor_pos - player_t.translation.truncate())
-                                            .extend(0.0)
-                                            .normalize()
-                                            .rotate_2d(PI * i as f32 / 16.0)
-                                            * 360.0,
-                                    ))
-                                    .insert(CollisionShape::Sphere { radius: 8.0 })
-                                    .insert(CollisionLayers::new(
-                                        GamePhysicsLayer::PlayerAttack,
-                                        GamePhysicsLayer::Enemy,
-                                    ))
-                                    .insert(DespawnTimer(Timer::from_seconds(1.5, false)))
-                                    .insert(DamagesEnemy {
-                                        damage: 2.0,
-                                        induces_fear: false,
-                                    })
-                                    .with_children(|parent| {
-                                        parent
-                                            .spawn()
-                                            .insert(GlobalTransform::default())
-                                            .insert(Transform::default())
-                                            .insert(RigidBody::Sensor)
-                                            .insert(CollisionShape::Sphere { radius: 16.0 })
-                                            .insert(CollisionLayers::new(
-                                                GamePhysicsLayer::PlayerAttack,
-                                                GamePhysicsLayer::Enemy,
-                                            ))
-                                            .insert(DamagesEnemy {
-                                                damage: 1.0,
-                                                induces_fear: false,
-                                            });
-                                    });
-                            }
-                            audio_player.play(audio.fireball.clone());
-                            spell_data.cooldowns.fireball.reset();
+                    PlayerSpell::Fireball | PlayerSpell::FearWave => {
+                        // Charged spells only start charging once they could be cast
+                        let ready = spell_data
+                            .cooldowns
+                            .for_spell(spell_data.selected)
+                            .finished()
+                            && mana.current >= mana_cost;
+                        if spell_data.charge.is_some() || ready {
+                            let charge = spell_data.charge.unwrap_or(0.0) + time.delta_seconds();
+                            spell_data.charge = Some(charge.min(MAX_CHARGE));
                         }
                     }
                     PlayerSpell::LightningStrike => {
-                        if spell_data.cooldowns.lightning_strike.finished() {
-                            commands
-                                .spawn_bundle(SpriteBundle {
-                                    texture: sprites.lightning_bolt.clone(),
-                                    transform: Transform {
-                                        translation: Vec3::new(
-                                            cursor_pos.x,
-                                            cursor_pos.y + SCREEN_HEIGHT + 24.0,
-                                            0.1,
-                                        ),
-                                        scale: Vec3::new(2.0, 2.0, 0.0),
-                                        ..Default::default()
-                                    },
-                                    ..Default::default()
-                                })
-                                .insert(RigidBody::Sensor)
-                                .insert(LightningStrikeBolt {
-                                    end_y: cursor_pos.y,
-                                });
+                        if spell_data.cooldowns.lightning_strike.finished()
+                            && mana.try_spend(mana_cost)
+                        {
+                            spawn_lightning_bolt(
+                                &mut commands,
+                                &sprites,
+                                aim_pos,
+                                LightningStrikeBolt::strike(aim_pos.y),
+                            );
                             spell_data.cooldowns.lightning_strike.reset();
                         }
                     }
-                    PlayerSpell::FearWave => {
-                        if spell_data.cooldowns.fear_wave.finished() {
+                    PlayerSpell::ChainLightning => {
+                        if spell_data.cooldowns.chain_lightning.finished()
+                            && mana.try_spend(mana_cost)
+                        {
+                            spawn_lightning_bolt(
+                                &mut commands,
+                                &sprites,
+                                aim_pos,
+                                LightningStrikeBolt {
+                                    radius: CHAIN_LIGHTNING_RADIUS,
+                                    splash: None,
+                                    chain: Some(ChainLightning {
+                                        jumps: 5,
+                                        range: 160.0,
+                                        falloff: 0.75,
+                                    }),
+                                    ..LightningStrikeBolt::strike(aim_pos.y)
+                                },
+                            );
+                            spell_data.cooldowns.chain_lightning.reset();
+                        }
+                    }
+                    PlayerSpell::LightningStorm => {
+                        if spell_data.cooldowns.lightning_storm.finished()
+                            && mana.try_spend(mana_cost)
+                        {
+                            commands.spawn().insert(LightningStorm {
+                                center: aim_pos,
+                                radius: LIGHTNING_STORM_RADIUS,
+                                strikes: 8,
+                                interval: Timer::from_seconds(0.3, true),
+                                bolt: LightningStrikeBolt {
+                                    radius: 64.0,
+                                    damage: 2.0,
+                                    splash: None,
+                                    effect: Some((StatusEffectKind::Slowed, 2.0)),
+                                    ..LightningStrikeBolt::strike(aim_pos.y)
+                                },
+                            });
+                            spell_data.cooldowns.lightning_storm.reset();
+                        }
+                    }
+                    PlayerSpell::RaiseDead => {
+                        if spell_data.cooldowns.raise_dead.finished() && mana.try_spend(mana_cost) {
+                            for (corpse, corpse_t, remains) in
+                                q_corpses.iter().filter(|(_, t, _)| {
+                                    t.translation.truncate().distance(aim_pos) <= RAISE_DEAD_RADIUS
+                                })
+                            {
+                                commands.entity(corpse).despawn();
+                                spawn_minion(
+                                    &mut commands,
+                                    &sprites,
+                                    remains.0,
+                                    corpse_t.translation.truncate().extend(0.1),
+                                );
+                            }
+                            // Grasping hands hold nearby soldiers in place
+                            for (_, mut effects) in q_enemies.iter_mut().filter(|(t, _)| {
+                                t.translation.truncate().distance(aim_pos) <= RAISE_DEAD_RADIUS
+                            }) {
+                                effects.apply(StatusEffectKind::Rooted, 2.0);
+                            }
                             commands
-                                .spawn_bundle(SpriteBundle {
-                                    texture: sprites.fear_wave.clone(),
-                                    sprite: Sprite {
-                                        color: Color::rgba(1.0, 1.0, 1.0, 0.3),
+                                .spawn_bundle(SpriteSheetBundle {
+                                    texture_atlas: sprites.lightning_explosion.clone(),
+                                    sprite: TextureAtlasSprite {
+                                        color: Color::rgba(0.5, 1.0, 0.6, 0.5),
                                         ..Default::default()
                                     },
                                     transform: Transform {
-                                        translation: player_t.translation,
-                                        scale: Vec3::new(2.0, 2.0, 0.0),
-                                        rotation: Quat::from_rotation_z(
-                                            cursor_pos
-                                                .extend(0.0)
-                                                .angle_between_points(player_t.translation),
-                                        ),
+                                        translation: aim_pos.extend(0.6),
+                                        scale: Vec3::new(3.0, 3.0, 0.0),
+                                        ..Default::default()
                                     },
                                     ..Default::default()
                                 })
-                                .insert(RigidBody::KinematicVelocityBased)
-                                .insert(CollisionShape::Cuboid {
-                                    half_extends: Vec3::new(16.0, 64.0, 0.0),
-                                    border_radius: None,
+                                .insert(Animated {
+                                    frames: 4,
+                                    timer: Timer::from_seconds(1.0 / 30.0, true),
                                 })
-                                .insert(Velocity::from_linear(
-                                    (cursor_pos - player_t.translation.truncate())
-                                        .extend(0.0)
-                                        .normalize()
-                                        * 240.0,
-                                ))
-                                .insert(CollisionLayers::new(
-                                    GamePhysicsLayer::PlayerAttack,
-                                    GamePhysicsLayer::Enemy,
-                                ))
-                                .insert(DamagesEnemy {
-                                    damage: 0.2,
-                                    induces_fear: true,
-                                })
-                                .insert(DespawnTimer(Timer::from_seconds(4.0, false)));
+                                .insert(DespawnTimer(Timer::from_seconds(0.25, false)));
+                            audio_player.play(audio.fear_wave.clone());
+                            spell_data.cooldowns.raise_dead.reset();
+                        }
+                    }
+                }
+            }
+            spell_data.no_shoot_delay.reset();
+            spell_data.no_shoot_penalty.reset();
+        } else if let Some(charge) = spell_data.charge.take() {
+            if let Some(aim_pos) = aim.0 {
+                let power = charge / MAX_CHARGE;
+                let mana_cost = spell_data.selected.mana_cost();
+                match spell_data.selected {
+                    PlayerSpell::Fireball => {
+                        if mana.try_spend(mana_cost) {
+                            cast_fireball(
+                                &mut commands,
+                                &sprites,
+                                player_t.translation,
+                                aim_pos,
+                                power,
+                                run_state.modifiers,
+                            );
+                            audio_player.play(audio.fireball.clone());
+                            spell_data.cooldowns.fireball.reset();
+                        }
+                    }
+                    PlayerSpell::FearWave => {
+                        if mana.try_spend(mana_cost) {
+                            cast_fear_wave(
+                                &mut commands,
+                                &sprites,
+                                player_t.translation,
+                                aim_pos,
+                                power,
+                            );
                             audio_player.play(audio.fear_wave.clone());
                             spell_data.cooldowns.fear_wave.reset();
                         }
                     }
+                    _ => (),
                 }
             }
             spell_data.no_shoot_delay.reset();
@@ -254,6 +492,79 @@ pub fn player_shoot(
     }
 }
 
+/// Casts three fireballs spreading out towards `target`, growing larger with `power`.
+fn cast_fireball(
+    commands: &mut Commands,
+    sprites: &GameSprites,
+    origin: Vec3,
+    target: Vec2,
+    power: f32,
+    modifiers: ProjectileModifiers,
+) {
+    let direction = (target - origin.truncate()).extend(0.0).normalize();
+    let modifiers = ProjectileModifiers {
+        pierce: modifiers.pierce + FIREBALL_PIERCE,
+        ..modifiers
+    };
+    for i in -1..=1 {
+        spawn_fireball(
+            commands,
+            sprites,
+            origin,
+            direction.rotate_2d(FIREBALL_SPREAD * i as f32) * 360.0,
+            modifiers,
+            Vec::new(),
+            1.0 + power,
+        );
+    }
+}
+
+/// Sends a wave of terror towards `target`, growing wider and lasting longer with `power`.
+fn cast_fear_wave(
+    commands: &mut Commands,
+    sprites: &GameSprites,
+    origin: Vec3,
+    target: Vec2,
+    power: f32,
+) {
+    let width = 1.0 + power;
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: sprites.fear_wave.clone(),
+            sprite: Sprite {
+                color: Color::rgba(1.0, 1.0, 1.0, 0.3),
+                ..Default::default()
+            },
+            transform: Transform {
+                translation: origin,
+                scale: Vec3::new(2.0, 2.0 * width, 0.0),
+                rotation: Quat::from_rotation_z(target.extend(0.0).angle_between_points(origin)),
+            },
+            ..Default::default()
+        })
+        .insert(RigidBody::KinematicVelocityBased)
+        .insert(CollisionShape::Cuboid {
+            half_extends: Vec3::new(16.0, FEAR_WAVE_WIDTH / 2.0 * width, 0.0),
+            border_radius: None,
+        })
+        .insert(Velocity::from_linear(
+            (target - origin.truncate()).extend(0.0).normalize() * 240.0,
+        ))
+        .insert(CollisionLayers::new(
+            GamePhysicsLayer::PlayerAttack,
+            GamePhysicsLayer::Enemy,
+        ))
+        .insert(DamagesEnemy {
+            damage: 0.2,
+            damage_type: DamageType::Shadow,
+            effect: Some((StatusEffectKind::Terrified, 4.0 * (1.0 + power))),
+        })
+        .insert(DespawnTimer(Timer::from_seconds(
+            4.0 * (1.0 + power),
+            false,
+        )));
+}
+
 pub fn tick_attack_cooldowns(
     mut q_player: Query<&mut PlayerSpellData, With<Player>>,
     time: Res<Time>,
@@ -265,30 +576,65 @@ pub fn tick_attack_cooldowns(
 
 pub fn switch_active_spell(
     mut q_player: Query<&mut PlayerSpellData, With<Player>>,
-    keyboard_input: Res<Input<KeyCode>>,
+    actions: Res<Input<Action>>,
     mut change_spell: EventWriter<ChangeSpellEvent>,
+    run_state: Res<RunState>,
 ) {
     if let Some(mut spell_data) = q_player.iter_mut().next() {
-        if keyboard_input.just_pressed(KeyCode::E) {
-            spell_data.selected = spell_data.selected.next();
+        let unlocked = &run_state.unlocked_spells;
+        let previous = spell_data.selected;
+        let hotkey_spell = (0..SPELL_SLOTS)
+            .find(|slot| actions.just_pressed(Action::SelectSpell(*slot)))
+            .and_then(|slot| run_state.known_spells().get(slot).copied());
+        if actions.just_pressed(Action::NextSpell) {
+            spell_data.selected = spell_data.selected.next(unlocked);
             change_spell.send(ChangeSpellEvent(spell_data.selected));
-        } else if keyboard_input.just_pressed(KeyCode::Q) {
-            spell_data.selected = spell_data.selected.previous();
+        } else if actions.just_pressed(Action::PreviousSpell) {
+            spell_data.selected = spell_data.selected.previous(unlocked);
             change_spell.send(ChangeSpellEvent(spell_data.selected));
+        } else if let Some(spell) = hotkey_spell.filter(|s| *s != spell_data.selected) {
+            spell_data.selected = spell;
+            change_spell.send(ChangeSpellEvent(spell));
+        }
+        if spell_data.selected != previous {
+            // Switching spells lets go of whatever was being charged
+            spell_data.charge = None;
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
 pub fn register_player_damage(
-    mut q_player: Query<&mut Health, With<Player>>,
+    mut commands: Commands,
+    mut q_player: Query<
+        (
+            Entity,
+            &mut Health,
+            &mut Visibility,
+            Option<&Invulnerable>,
+            Option<&Reforming>,
+        ),
+        With<Player>,
+    >,
+    q_phylactery: Query<Entity, With<Phylactery>>,
     mut damages: EventReader<DamagePlayerEvent>,
     mut state: ResMut<State<GameState>>,
     mut day_end_writer: EventWriter<EndDayEvent>,
     mut current_day: ResMut<CurrentDay>,
+    mut morale: ResMut<EnemyMorale>,
+    rules: Res<GameRules>,
     audio: Res<GameAudio>,
     audio_player: Res<Audio>,
 ) {
-    if let Some(mut player) = q_player.iter_mut().next() {
+    if let Some((ent, mut player, mut visibility, invulnerable, reforming)) =
+        q_player.iter_mut().next()
+    {
+        if reforming.is_some() || invulnerable.map_or(false, |i| !i.0.finished()) {
+            // Still drain the events, so they aren't applied once invulnerability ends
+            damages.iter().for_each(drop);
+            return;
+        }
         let mut damaged = false;
         for damage in damages.iter() {
             damaged = true;
@@ -297,35 +643,95 @@ pub fn register_player_damage(
         }
         if damaged {
             audio_player.play(audio.player_hurt.clone());
+            // A brief grace period, so that a swarm of knights can't hit all at once
+            commands
+                .entity(ent)
+                .insert(Invulnerable(Timer::from_seconds(
+                    POST_HIT_INVULNERABILITY,
+                    false,
+                )));
         }
         if player.current <= 0.0 {
-            day_end_writer.send(EndDayEvent {
-                reason: DayEndReason::PlayerDeath,
-            });
-            state.set(GameState::MoraleStatus).unwrap();
+            current_day.deaths += 1;
+            if rules.respawn_at_phylactery && !q_phylactery.is_empty() {
+                // The day goes on without the lich until it reforms
+                morale.change += DEATH_MORALE_BONUS;
+                visibility.is_visible = false;
+                // Nothing can touch it while it's gone, so enemies walk straight through
+                commands
+                    .entity(ent)
+                    .insert(Reforming(Timer::from_seconds(REFORM_DELAY, false)))
+                    .insert(CollisionLayers::none());
+            } else {
+                day_end_writer.send(EndDayEvent {
+                    reason: DayEndReason::PlayerDeath,
+                });
+                state.set(GameState::MoraleStatus).unwrap();
+            }
+        }
+    }
+}
+
+/// Brings the lich back at its phylactery once it has finished reforming.
+pub fn reform_player(
+    mut commands: Commands,
+    mut q_player: Query<
+        (
+            Entity,
+            &mut Reforming,
+            &mut Health,
+            &mut Transform,
+            &mut Visibility,
+        ),
+        With<Player>,
+    >,
+    q_phylactery: Query<&Transform, (With<Phylactery>, Without<Player>)>,
+    time: Res<Time>,
+) {
+    for (ent, mut reforming, mut health, mut transform, mut visibility) in q_player.iter_mut() {
+        if !reforming.0.tick(time.delta()).finished() {
+            continue;
         }
+        if let Some(phylactery) = q_phylactery.iter().next() {
+            transform.translation.x = phylactery.translation.x;
+            transform.translation.y = phylactery.translation.y - 64.0;
+        }
+        health.current = health.maximum;
+        visibility.is_visible = true;
+        commands
+            .entity(ent)
+            .remove::<Reforming>()
+            .insert(player_collision_layers())
+            .insert(Invulnerable(Timer::from_seconds(
+                REFORM_INVULNERABILITY,
+                false,
+            )));
     }
 }
 
 pub fn update_health_bar(
-    mut q_ui: Query<(&mut Sprite, &mut Transform, &Ui), Without<Player>>,
-    q_player: Query<(&Health, &Transform), With<Player>>,
+    mut q_ui: Query<(&mut Sprite, &mut Transform, &mut Visibility, &Ui), Without<Player>>,
+    q_player: Query<(&Health, &Transform, Option<&Reforming>), With<Player>>,
+    palette: Res<ColorPalette>,
 ) {
-    for (mut sprite, mut h_transform) in q_ui.iter_mut().filter_map(|(s, t, i)| match i {
-        Ui::HealthBarMain => Some((s, t)),
-        _ => None,
-    }) {
-        if let Some((health, p_transform)) = q_player.iter().next() {
+    for (mut sprite, mut h_transform, mut visibility) in
+        q_ui.iter_mut().filter_map(|(s, t, v, i)| match i {
+            Ui::HealthBarMain => Some((s, t, v)),
+            _ => None,
+        })
+    {
+        if let Some((health, p_transform, reforming)) = q_player.iter().next() {
+            visibility.is_visible = reforming.is_none();
             sprite.custom_size = Some(Vec2::new(
                 (health.current / health.maximum).max(0.0) * 100.0,
                 12.0,
             ));
             if health.current >= health.maximum - 3.0 {
-                sprite.color = Color::CYAN;
+                sprite.color = palette.health_full;
             } else if health.current <= health.maximum * 0.25 {
-                sprite.color = Color::RED;
+                sprite.color = palette.health_low;
             } else {
-                sprite.color = Color::GREEN;
+                sprite.color = palette.health;
             }
             h_transform.translation.x = p_transform.translation.x;
             h_transform.translation.y = p_transform.translation.y - 60.0;
@@ -333,11 +739,80 @@ pub fn update_health_bar(
     }
 }
 
+#[allow(clippy::type_complexity)]
+pub fn update_mana_bar(
+    mut q_ui: Query<(&mut Sprite, &mut Transform, &mut Visibility, &Ui), Without<Player>>,
+    q_player: Query<(&Mana, &PlayerSpellData, &Transform, Option<&Reforming>), With<Player>>,
+) {
+    for (mut sprite, mut m_transform, mut visibility) in
+        q_ui.iter_mut().filter_map(|(s, t, v, i)| match i {
+            Ui::ManaBar => Some((s, t, v)),
+            _ => None,
+        })
+    {
+        if let Some((mana, spell_data, p_transform, reforming)) = q_player.iter().next() {
+            visibility.is_visible = reforming.is_none();
+            sprite.custom_size = Some(Vec2::new(
+                (mana.current / mana.maximum).max(0.0) * 100.0,
+                6.0,
+            ));
+            // Dim the bar while the selected spell can't be afforded
+            if mana.current >= spell_data.selected.mana_cost() {
+                sprite.color = Color::rgb(0.3, 0.5, 1.0);
+            } else {
+                sprite.color = Color::rgb(0.25, 0.25, 0.5);
+            }
+            m_transform.translation.x = p_transform.translation.x;
+            m_transform.translation.y = p_transform.translation.y - 70.0;
+        }
+    }
+}
+
+pub fn update_charge_meter(
+    mut q_ui: Query<(&mut Sprite, &mut Transform, &mut Visibility, &Ui), Without<Player>>,
+    q_player: Query<(&PlayerSpellData, &Transform), With<Player>>,
+) {
+    for (mut sprite, mut c_transform, mut visibility) in
+        q_ui.iter_mut().filter_map(|(s, t, v, i)| match i {
+            Ui::ChargeMeter => Some((s, t, v)),
+            _ => None,
+        })
+    {
+        if let Some((spell_data, p_transform)) = q_player.iter().next() {
+            visibility.is_visible = spell_data.charge.is_some();
+            let power = spell_data.charge.unwrap_or(0.0) / MAX_CHARGE;
+            sprite.custom_size = Some(Vec2::new(power * 48.0, 4.0));
+            // Flash white once fully charged
+            if power >= 1.0 {
+                sprite.color = Color::WHITE;
+            } else {
+                sprite.color = Color::rgb(1.0, 0.6, 0.2);
+            }
+            c_transform.translation.x = p_transform.translation.x;
+            c_transform.translation.y = p_transform.translation.y + 40.0;
+        }
+    }
+}
+
+/// Regenerates mana, faster the more souls have been harvested today.
+pub fn regenerate_mana(
+    mut q_player: Query<&mut Mana, With<Player>>,
+    morale: Res<EnemyMorale>,
+    time: Res<Time>,
+) {
+    for mut mana in q_player.iter_mut() {
+        let soul_bonus = (morale.enemies_killed as f32 * 0.25).min(15.0);
+        mana.current = (mana.current + (mana.regen + soul_bonus) * time.delta().as_secs_f32())
+            .min(mana.maximum);
+    }
+}
+
 #[allow(clippy::type_complexity)]
 pub fn update_spell_display(
     mut q_ui: Query<
         (
             &mut Handle<Image>,
+            &mut Sprite,
             &mut Visibility,
             &mut InvisTimer,
             &mut Transform,
@@ -345,24 +820,33 @@ pub fn update_spell_display(
         ),
         Without<Player>,
     >,
+    mut q_text_ui: Query<
+        (
+            &mut Text,
+            &mut Visibility,
+            &mut InvisTimer,
+            &mut Transform,
+            &Ui,
+        ),
+        (Without<Player>, Without<Sprite>),
+    >,
     q_player: Query<&Transform, With<Player>>,
     mut change_spell: EventReader<ChangeSpellEvent>,
     sprites: Res<GameSprites>,
+    run_state: Res<RunState>,
 ) {
     let spell_changed = change_spell.iter().next();
     let player = q_player.iter().next();
-    for (texture, mut visibility, mut timer, mut transform) in
-        q_ui.iter_mut().filter_map(|(h, v, i, t, u)| match u {
-            Ui::CurrentSpell => Some((h, v, i, t)),
+    for (texture, mut sprite, mut visibility, mut timer, mut transform) in
+        q_ui.iter_mut().filter_map(|(h, s, v, i, t, u)| match u {
+            Ui::CurrentSpell => Some((h, s, v, i, t)),
             _ => None,
         })
     {
         if let Some(spell) = spell_changed {
-            *texture.into_inner() = match spell.0 {
-                PlayerSpell::Fireball => sprites.spell_icon_fireball.clone(),
-                PlayerSpell::LightningStrike => sprites.spell_icon_lightning.clone(),
-                PlayerSpell::FearWave => sprites.spell_icon_fear.clone(),
-            };
+            let (icon, color) = spell.0.icon(&sprites);
+            *texture.into_inner() = icon;
+            sprite.color = color;
             timer.0.reset();
             visibility.is_visible = true;
         }
@@ -371,12 +855,35 @@ pub fn update_spell_display(
             transform.translation.y = player.translation.y + 60.0;
         }
     }
+
+    for (mut text, mut visibility, mut timer, mut transform) in
+        q_text_ui.iter_mut().filter_map(|(x, v, i, t, u)| match u {
+            Ui::SpellModifiers => Some((x, v, i, t)),
+            _ => None,
+        })
+    {
+        if let Some(spell) = spell_changed {
+            // Only fireballs are affected by projectile modifiers for now
+            text.sections[0].value = match spell.0 {
+                PlayerSpell::Fireball => run_state.modifiers.label(),
+                _ => String::new(),
+            };
+            timer.0.reset();
+            visibility.is_visible = true;
+        }
+        if let Some(player) = player {
+            transform.translation.x = player.translation.x;
+            transform.translation.y = player.translation.y + 92.0;
+        }
+    }
 }
 
 pub fn display_player_controls(
     mut commands: Commands,
     current_day: Res<CurrentDay>,
     fonts: Res<GameFonts>,
+    device: Res<InputDevice>,
+    input_map: Res<InputMap>,
 ) {
     if current_day.day == 1 {
         commands
@@ -393,31 +900,48 @@ pub fn display_player_controls(
             })
             .insert(DespawnTimer(Timer::from_seconds(7.0, false)))
             .with_children(|parent| {
-                parent.spawn_bundle(TextBundle {
-                    text: Text {
-                        sections: vec![TextSection {
-                            value: "WASD: Move, LMB (Click/Hold): Attack, QE: Change Spells"
-                                .to_string(),
-                            style: TextStyle {
-                                font: fonts.main.clone(),
-                                font_size: 32.0,
-                                color: Color::WHITE,
+                parent
+                    .spawn_bundle(TextBundle {
+                        text: Text {
+                            sections: vec![TextSection {
+                                value: input_map.controls_hint(*device),
+                                style: TextStyle {
+                                    font: fonts.main.clone(),
+                                    font_size: 32.0,
+                                    color: Color::WHITE,
+                                },
+                            }],
+                            alignment: TextAlignment {
+                                horizontal: HorizontalAlign::Center,
+                                vertical: VerticalAlign::Center,
                             },
-                        }],
-                        alignment: TextAlignment {
-                            horizontal: HorizontalAlign::Center,
-                            vertical: VerticalAlign::Center,
                         },
-                    },
-                    style: Style {
-                        margin: Rect {
-                            top: Val::Px(SCREEN_HEIGHT * 0.3),
+                        style: Style {
+                            margin: Rect {
+                                top: Val::Px(SCREEN_HEIGHT * 0.3),
+                                ..Default::default()
+                            },
                             ..Default::default()
                         },
                         ..Default::default()
-                    },
-                    ..Default::default()
-                });
+                    })
+                    .insert(Ui::ControlsHint);
             });
     }
 }
+
+/// Rewrites the controls hint for whichever device the player picked up last.
+pub fn update_controls_hint(
+    mut q_text_ui: Query<(&Ui, &mut Text)>,
+    device: Res<InputDevice>,
+    input_map: Res<InputMap>,
+) {
+    if !device.is_changed() && !input_map.is_changed() {
+        return;
+    }
+    for (ui, mut text) in q_text_ui.iter_mut() {
+        if let Ui::ControlsHint = ui {
+            text.sections[0].value = input_map.controls_hint(*device);
+        }
+    }
+}
@@ -1,61 +1,86 @@
 use crate::common::{
-    Animated, DamagesEnemy, DespawnTimer, Enemy, EnemyAI, GameAudio, GamePhysicsLayer, GameSprites,
-    Health, LightningStrikeBolt, SCREEN_HEIGHT,
+    Animated, DamageEnemyEvent, DamageSource, DamageType, DamagesEnemy, DespawnTimer, Enemy,
+    GameAudio, GamePhysicsLayer, GameSprites, LightningStorm, LightningStrikeBolt, PlayerAim,
+    PlayerAttackHitEvent, PlayerProjectile, ProjectileModifiers, StatusEffectKind, Vec3Utils,
+    SCREEN_HEIGHT, WORLD_HEIGHT, WORLD_WIDTH,
 };
 use bevy::prelude::*;
 use bevy_kira_audio::Audio;
 use heron::prelude::*;
+use std::f32::consts::PI;
+
+pub fn spawn_fireball(
+    commands: &mut Commands,
+    sprites: &GameSprites,
+    position: Vec3,
+    velocity: Vec3,
+    modifiers: ProjectileModifiers,
+    hits: Vec<Entity>,
+    size: f32,
+) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: sprites.fireball.clone(),
+            transform: Transform {
+                translation: position,
+                scale: Vec3::new(2.0 * size, 2.0 * size, 0.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(RigidBody::KinematicVelocityBased)
+        .insert(Velocity::from_linear(velocity))
+        .insert(CollisionShape::Sphere {
+            radius: 16.0 * size,
+        })
+        .insert(CollisionLayers::new(
+            GamePhysicsLayer::PlayerAttack,
+            GamePhysicsLayer::Enemy,
+        ))
+        .insert(DespawnTimer(Timer::from_seconds(1.5, false)))
+        // Covers both the direct hit and the scorching around it, so every hit counts towards pierce
+        .insert(DamagesEnemy {
+            damage: 3.0 * size,
+            damage_type: DamageType::Fire,
+            effect: Some((StatusEffectKind::Burning, 3.0)),
+        })
+        .insert(modifiers)
+        .insert(PlayerProjectile {
+            inherited_hits: hits.len(),
+            hits,
+        });
+}
 
 pub fn check_projectile_collision(
-    mut collision_events: EventReader<CollisionEvent>,
-    mut q_enemies: Query<(&mut Health, &mut Enemy)>,
+    mut hit_events: EventReader<PlayerAttackHitEvent>,
+    q_enemies: Query<&Enemy>,
     q_damages: Query<&DamagesEnemy>,
+    mut q_projectiles: Query<(&mut PlayerProjectile, &ProjectileModifiers)>,
+    mut damage_writer: EventWriter<DamageEnemyEvent>,
     audio: Res<GameAudio>,
     audio_player: Res<Audio>,
 ) {
-    fn is_projectile(layers: CollisionLayers) -> bool {
-        layers.contains_group(GamePhysicsLayer::PlayerAttack)
-            && !layers.contains_group(GamePhysicsLayer::Enemy)
-    }
-    fn is_enemy(layers: CollisionLayers) -> bool {
-        layers.contains_group(GamePhysicsLayer::Enemy)
-            && !layers.contains_group(GamePhysicsLayer::PlayerAttack)
-    }
-
     let mut damage_dealt = false;
-    for (e_enemy, e_damager) in collision_events
-        .iter()
-        .filter(|e| e.is_started())
-        .filter_map(|event| {
-            let (entity_1, entity_2) = event.rigid_body_entities();
-            let (layers_1, layers_2) = event.collision_layers();
-            if is_enemy(layers_1) && is_projectile(layers_2) {
-                Some((entity_1, entity_2))
-            } else if is_enemy(layers_2) && is_projectile(layers_1) {
-                Some((entity_2, entity_1))
-            } else {
-                None
-            }
-        })
+    for PlayerAttackHitEvent {
+        attack: e_damager,
+        enemy: e_enemy,
+    } in hit_events.iter().copied()
     {
-        if let Ok((mut health, mut enemy)) = q_enemies.get_mut(e_enemy) {
-            if let Ok(damage) = q_damages.get(e_damager) {
-                damage_dealt = true;
-                health.current -= damage.damage;
-                match enemy.ai {
-                    EnemyAI::Afraid { speed: _ } => (),
-                    EnemyAI::ChasesPlayer { speed } => {
-                        if damage.induces_fear || health.current <= enemy.fear_threshold {
-                            enemy.ai = EnemyAI::Afraid { speed };
-                        }
-                    }
-                    _ => {
-                        if damage.induces_fear || health.current <= enemy.fear_threshold {
-                            enemy.ai = EnemyAI::Afraid { speed: 120.0 };
-                        }
-                    }
-                }
+        if let Ok((mut projectile, modifiers)) = q_projectiles.get_mut(e_damager) {
+            if projectile.own_hits() > modifiers.pierce || projectile.hits.contains(&e_enemy) {
+                continue;
             }
+            projectile.hits.push(e_enemy);
+        }
+        if let (Ok(_), Ok(damage)) = (q_enemies.get(e_enemy), q_damages.get(e_damager)) {
+            damage_dealt = true;
+            damage_writer.send(DamageEnemyEvent {
+                enemy: e_enemy,
+                damage: damage.damage,
+                damage_type: damage.damage_type,
+                effect: damage.effect,
+                source: DamageSource::Spell,
+            });
         }
     }
     if damage_dealt {
@@ -63,9 +88,192 @@ pub fn check_projectile_collision(
     }
 }
 
+/// Steers homing projectiles and bounces projectiles off the edges of the arena.
+pub fn update_player_projectiles(
+    mut q_projectiles: Query<(
+        &mut ProjectileModifiers,
+        &Transform,
+        &mut Velocity,
+        &mut DespawnTimer,
+    )>,
+    aim: Res<PlayerAim>,
+    time: Res<Time>,
+) {
+    for (mut modifiers, transform, mut velocity, mut despawn_timer) in q_projectiles.iter_mut() {
+        let position = transform.translation.truncate();
+        if let Some(aim_pos) = aim.0.filter(|_| modifiers.homing > 0.0) {
+            let current = velocity.linear.truncate();
+            let wanted = aim_pos - position;
+            let turn = current.angle_between(wanted);
+            if turn.is_finite() {
+                let max_turn = modifiers.homing * time.delta().as_secs_f32();
+                velocity.linear = velocity.linear.rotate_2d(turn.clamp(-max_turn, max_turn));
+            }
+        }
+
+        if modifiers.bounces > 0 {
+            let bounds = Vec2::new(WORLD_WIDTH / 2.0, WORLD_HEIGHT / 2.0);
+            let mut bounced = false;
+            if position.x.abs() >= bounds.x && position.x * velocity.linear.x > 0.0 {
+                velocity.linear.x = -velocity.linear.x;
+                bounced = true;
+            }
+            if position.y.abs() >= bounds.y && position.y * velocity.linear.y > 0.0 {
+                velocity.linear.y = -velocity.linear.y;
+                bounced = true;
+            }
+            if bounced {
+                modifiers.bounces -= 1;
+                despawn_timer.0.reset();
+            }
+        }
+    }
+}
+
+/// Removes projectiles that have hit as many enemies as they can pierce, splitting them if able.
+pub fn despawn_spent_projectiles(
+    mut commands: Commands,
+    q_projectiles: Query<(
+        Entity,
+        &PlayerProjectile,
+        &ProjectileModifiers,
+        &Transform,
+        &Velocity,
+    )>,
+    sprites: Res<GameSprites>,
+) {
+    for (ent, projectile, modifiers, transform, velocity) in q_projectiles.iter() {
+        if projectile.own_hits() <= modifiers.pierce {
+            continue;
+        }
+        if modifiers.splits > 0 {
+            let split_modifiers = ProjectileModifiers {
+                splits: modifiers.splits - 1,
+                ..*modifiers
+            };
+            for angle in [-PI / 6.0, PI / 6.0] {
+                spawn_fireball(
+                    &mut commands,
+                    &sprites,
+                    transform.translation,
+                    velocity.linear.rotate_2d(angle),
+                    split_modifiers,
+                    projectile.hits.clone(),
+                    transform.scale.x / 2.0,
+                );
+            }
+        }
+        commands.entity(ent).despawn_recursive();
+    }
+}
+
+/// Drops a lightning bolt from above the screen onto the target.
+pub fn spawn_lightning_bolt(
+    commands: &mut Commands,
+    sprites: &GameSprites,
+    target: Vec2,
+    bolt: LightningStrikeBolt,
+) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: sprites.lightning_bolt.clone(),
+            transform: Transform {
+                translation: Vec3::new(target.x, target.y + SCREEN_HEIGHT + 24.0, 0.1),
+                scale: Vec3::new(2.0, 2.0, 0.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(RigidBody::Sensor)
+        .insert(LightningStrikeBolt {
+            end_y: target.y,
+            ..bolt
+        });
+}
+
+fn spawn_lightning_sensor(
+    parent: &mut ChildBuilder,
+    radius: f32,
+    damage: f32,
+    effect: Option<(StatusEffectKind, f32)>,
+) {
+    parent
+        .spawn()
+        .insert(GlobalTransform::default())
+        .insert(Transform::default())
+        .insert(RigidBody::Sensor)
+        .insert(CollisionShape::Sphere { radius })
+        .insert(CollisionLayers::new(
+            GamePhysicsLayer::PlayerAttack,
+            GamePhysicsLayer::Enemy,
+        ))
+        .insert(DamagesEnemy {
+            damage,
+            damage_type: DamageType::Lightning,
+            effect,
+        });
+}
+
+/// Draws a jagged arc between two points, and damages whatever is standing at the end of it.
+fn spawn_chain_arc(
+    commands: &mut Commands,
+    from: Vec2,
+    to: Vec2,
+    damage: f32,
+    effect: Option<(StatusEffectKind, f32)>,
+) {
+    const KINKS: usize = 3;
+    let normal = (to - from).perp().normalize_or_zero();
+    let mut points = vec![from];
+    for i in 1..KINKS {
+        let along = from.lerp(to, i as f32 / KINKS as f32);
+        points.push(along + normal * alea::f32_in_range(-12.0, 12.0));
+    }
+    points.push(to);
+
+    for (start, end) in points.iter().zip(points.iter().skip(1)) {
+        let delta = *end - *start;
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(0.7, 0.85, 1.0, 0.9),
+                    custom_size: Some(Vec2::new(delta.length(), 3.0)),
+                    ..Default::default()
+                },
+                transform: Transform {
+                    translation: ((*start + *end) / 2.0).extend(0.6),
+                    rotation: Quat::from_rotation_z(delta.y.atan2(delta.x)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(DespawnTimer(Timer::from_seconds(0.15, false)));
+    }
+
+    commands
+        .spawn_bundle((
+            Transform::from_translation(to.extend(0.6)),
+            GlobalTransform::default(),
+        ))
+        .insert(RigidBody::Sensor)
+        .insert(CollisionShape::Sphere { radius: 12.0 })
+        .insert(CollisionLayers::new(
+            GamePhysicsLayer::PlayerAttack,
+            GamePhysicsLayer::Enemy,
+        ))
+        .insert(DamagesEnemy {
+            damage,
+            damage_type: DamageType::Lightning,
+            effect,
+        })
+        .insert(DespawnTimer(Timer::from_seconds(0.1, false)));
+}
+
+#[allow(clippy::type_complexity)]
 pub fn update_lightning_bolt(
     mut commands: Commands,
     mut q_lightning_bolt: Query<(Entity, &LightningStrikeBolt, &mut Transform)>,
+    q_enemies: Query<(Entity, &Transform), (With<Enemy>, Without<LightningStrikeBolt>)>,
     time: Res<Time>,
     sprites: Res<GameSprites>,
     audio: Res<GameAudio>,
@@ -74,6 +282,7 @@ pub fn update_lightning_bolt(
     for (ent, bolt, mut transform) in q_lightning_bolt.iter_mut() {
         transform.translation.y -= SCREEN_HEIGHT * 5.0 * time.delta().as_secs_f32();
         if transform.translation.y <= bolt.end_y {
+            let impact = Vec2::new(transform.translation.x, bolt.end_y);
             commands
                 .spawn_bundle(SpriteSheetBundle {
                     texture_atlas: sprites.lightning_explosion.clone(),
@@ -82,8 +291,8 @@ pub fn update_lightning_bolt(
                         ..Default::default()
                     },
                     transform: Transform {
-                        translation: Vec3::new(transform.translation.x, bolt.end_y, 0.6),
-                        scale: Vec3::new(3.0, 3.0, 0.0),
+                        translation: impact.extend(0.6),
+                        scale: Vec3::new(3.0 * bolt.radius / 96.0, 3.0 * bolt.radius / 96.0, 0.0),
                         ..Default::default()
                     },
                     ..Default::default()
@@ -92,36 +301,85 @@ pub fn update_lightning_bolt(
                     frames: 4,
                     timer: Timer::from_seconds(1.0 / 60.0, true),
                 })
-                .insert(RigidBody::Sensor)
-                .insert(CollisionShape::Sphere { radius: 96.0 })
-                .insert(CollisionLayers::new(
-                    GamePhysicsLayer::PlayerAttack,
-                    GamePhysicsLayer::Enemy,
-                ))
-                .insert(DamagesEnemy {
-                    damage: 3.0,
-                    induces_fear: false,
-                })
                 .insert(DespawnTimer(Timer::from_seconds(0.25, false)))
                 .with_children(|parent| {
-                    parent
-                        .spawn()
-                        .insert(GlobalTransform::default())
-                        .insert(Transform::default())
-                        .insert(RigidBody::Sensor)
-                        .insert(CollisionShape::Sphere { radius: 112.0 })
-                        .insert(CollisionLayers::new(
-                            GamePhysicsLayer::PlayerAttack,
-                            GamePhysicsLayer::Enemy,
-                        ))
-                        .insert(DamagesEnemy {
-                            damage: 2.0,
-                            induces_fear: false,
-                        });
+                    spawn_lightning_sensor(parent, bolt.radius, bolt.damage, bolt.effect);
+                    if let Some((extra_radius, damage)) = bolt.splash {
+                        spawn_lightning_sensor(
+                            parent,
+                            bolt.radius + extra_radius,
+                            damage,
+                            bolt.effect,
+                        );
+                    }
                 });
 
+            if let Some(chain) = &bolt.chain {
+                let distance_to = |from: Vec2, e: &Entity| {
+                    q_enemies.get(*e).map_or(f32::INFINITY, |(_, t)| {
+                        from.distance(t.translation.truncate())
+                    })
+                };
+                let mut remaining: Vec<Entity> = q_enemies.iter().map(|(e, _)| e).collect();
+                let first = remaining
+                    .iter()
+                    .copied()
+                    .filter(|e| distance_to(impact, e) <= bolt.radius)
+                    .min_by(|a, b| {
+                        distance_to(impact, a)
+                            .partial_cmp(&distance_to(impact, b))
+                            .unwrap()
+                    });
+                if let Some(first) = first {
+                    remaining.retain(|e| *e != first);
+                    let mut from = q_enemies.get(first).unwrap().1.translation.truncate();
+                    let mut damage = bolt.damage;
+                    for _ in 0..chain.jumps {
+                        let next = remaining
+                            .iter()
+                            .copied()
+                            .filter(|e| distance_to(from, e) <= chain.range)
+                            .min_by(|a, b| {
+                                distance_to(from, a)
+                                    .partial_cmp(&distance_to(from, b))
+                                    .unwrap()
+                            });
+                        let next = match next {
+                            Some(next) => next,
+                            None => break,
+                        };
+                        remaining.retain(|e| *e != next);
+                        let to = q_enemies.get(next).unwrap().1.translation.truncate();
+                        damage *= chain.falloff;
+                        spawn_chain_arc(&mut commands, from, to, damage, bolt.effect);
+                        from = to;
+                    }
+                }
+            }
+
             audio_player.play(audio.lightning_explosion.clone());
             commands.entity(ent).despawn();
         }
     }
 }
+
+pub fn update_lightning_storm(
+    mut commands: Commands,
+    mut q_storms: Query<(Entity, &mut LightningStorm)>,
+    time: Res<Time>,
+    sprites: Res<GameSprites>,
+) {
+    for (ent, mut storm) in q_storms.iter_mut() {
+        if storm.interval.tick(time.delta()).just_finished() {
+            let offset = Vec3::X.rotate_2d(alea::f32_in_range(0.0, PI * 2.0))
+                * storm.radius
+                * alea::f32().sqrt();
+            let target = storm.center + offset.truncate();
+            spawn_lightning_bolt(&mut commands, &sprites, target, storm.bolt.clone());
+            storm.strikes -= 1;
+        }
+        if storm.strikes == 0 {
+            commands.entity(ent).despawn();
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{
+    common::{ColorPalette, GameRules, Settings},
+    config::save_config,
+};
+use bevy::{prelude::*, window::WindowMode};
+use bevy_kira_audio::{Audio, AudioChannel};
+use std::time::{Duration, Instant};
+
+/// The config file the settings are kept in.
+pub const SETTINGS_CONFIG: &str = "settings";
+/// Nothing plays here yet, but it keeps music on its own volume once something does.
+pub const MUSIC_CHANNEL: &str = "music";
+
+fn window_mode(settings: &Settings) -> WindowMode {
+    if settings.fullscreen {
+        WindowMode::BorderlessFullscreen
+    } else {
+        WindowMode::Windowed
+    }
+}
+
+/// Describes the window the way the settings want it, for opening it at startup.
+pub fn window_descriptor(settings: &Settings, descriptor: WindowDescriptor) -> WindowDescriptor {
+    WindowDescriptor {
+        mode: window_mode(settings),
+        scale_factor_override: Some(settings.window_scale as f64),
+        vsync: settings.vsync,
+        ..descriptor
+    }
+}
+
+/// Pushes the settings out to the window, the audio and the game whenever they change.
+pub fn apply_settings(
+    settings: Res<Settings>,
+    mut windows: ResMut<Windows>,
+    audio: Res<Audio>,
+    mut rules: ResMut<GameRules>,
+    mut palette: ResMut<ColorPalette>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    audio.set_volume(settings.master_volume * settings.sfx_volume);
+    audio.set_volume_in_channel(
+        settings.master_volume * settings.music_volume,
+        &AudioChannel::new(MUSIC_CHANNEL.to_string()),
+    );
+
+    if let Some(window) = windows.get_primary_mut() {
+        let mode = window_mode(&settings);
+        if window.mode() != mode {
+            window.set_mode(mode);
+        }
+        let scale = Some(settings.window_scale as f64);
+        if window.scale_factor_override() != scale {
+            window.set_scale_factor_override(scale);
+        }
+        if window.vsync() != settings.vsync {
+            window.set_vsync(settings.vsync);
+        }
+    }
+
+    rules.respawn_at_phylactery = settings.respawn_at_phylactery;
+    *palette = if settings.colorblind {
+        ColorPalette::COLORBLIND
+    } else {
+        ColorPalette::STANDARD
+    };
+}
+
+/// Writes the settings back out whenever they're changed.
+pub fn save_settings(settings: Res<Settings>) {
+    if settings.is_changed() && !settings.is_added() {
+        save_config(SETTINGS_CONFIG, &*settings);
+    }
+}
+
+/// Sleeps off whatever is left of each frame's time slice, when a frame cap is set.
+pub fn limit_frame_rate(settings: Res<Settings>, mut last_frame: Local<Option<Instant>>) {
+    if let (Some(fps), Some(last_frame)) = (settings.frame_cap, *last_frame) {
+        let frame_time = Duration::from_secs_f64(1.0 / fps as f64);
+        let elapsed = last_frame.elapsed();
+        if elapsed < frame_time {
+            std::thread::sleep(frame_time - elapsed);
+        }
+    }
+    *last_frame = Some(Instant::now());
+}
@@ -1,37 +1,79 @@
 use crate::{
+    arena::{generate_arena, EnemyPaths, ARENA_TILE_SIZE, SPAWN_EDGES},
+    camera::{follow_player, reset_camera, setup_camera, shake_on_player_damage, zoom_camera},
+    collision::route_collisions,
     common::{
-        animate_sprites, check_despawn, check_invis, ChangeSpellEvent, CurrentDay, CurrentTime,
-        DamagePlayerEvent, DamagesEnemy, DayEndReason, EndDayEvent, EnemyMorale, GameAudio,
-        GameFonts, GameSprites, GameState, InGameUI, Label, MainCamera, NarrationViewed, Ui,
-        WaveCore, WaveManager, SCREEN_HEIGHT, SCREEN_WIDTH,
+        animate_sprites, check_despawn, check_invis, Action, ActiveGamepad, ChangeSpellEvent,
+        ColorPalette, Corpse, CurrentDay, CurrentTime, DamageEnemyEvent, DamagePlayerEvent,
+        DamagesEnemy, DayEndReason, DayReport, EndDayEvent, EnemyAttackHitEvent, EnemyContactEvent,
+        EnemyMorale, GameAudio, GameFonts, GameRules, GameSprites, GameState, InGameUI,
+        InputDevice, InputMap, Label, LightningStorm, MenuFocus, Minion, MinionCommandEvent,
+        MinionOrders, NarrationViewed, PlayerAim, PlayerAttackHitEvent, RunState, ScreenShake,
+        Settings, Ui, WaveCore, WaveManager, SCREEN_HEIGHT, SCREEN_WIDTH, WORLD_HEIGHT,
+        WORLD_WIDTH,
     },
+    config::load_config,
     enemy::{
-        check_enemy_player_collision, despawn_enemies, enemy_damage_player,
-        enemy_projectile_damage_player, spawn_enemy_wave, update_enemy, update_enemy_render,
-        update_enemy_shoot,
+        apply_enemy_damage, check_enemy_player_collision, despawn_enemies, enemy_damage_player,
+        enemy_projectile_damage_player, spawn_enemy_wave, update_enemy, update_enemy_paths,
+        update_enemy_render, update_enemy_shoot, update_status_effects,
+    },
+    hud::{
+        generate_reticle_texture, spawn_minimap, spawn_reticle, spawn_spell_bar, update_minimap,
+        update_offscreen_indicators, update_reticle, update_spell_bar,
+    },
+    input::{
+        detect_input_device, handle_gamepad_connections, save_input_map, update_action_state,
+        update_player_aim, CONTROLS_CONFIG,
     },
     menu::{
-        button_credits_back, button_game_over, button_main_menu, button_shift_narration,
-        button_start_day, despawn_menu, spawn_credits, spawn_game_over, spawn_main_menu,
-        spawn_menu, spawn_morale_status,
+        animate_spell_unlock, button_controls, button_credits_back, button_game_over,
+        button_main_menu, button_pause, button_settings, button_shift_narration, button_shop,
+        button_start_day, despawn_menu, despawn_menu_ui, navigate_menu, pause_game, resume_physics,
+        settle_day_end, spawn_controls, spawn_credits, spawn_game_over, spawn_main_menu,
+        spawn_menu, spawn_morale_status, spawn_pause_menu, spawn_settings, spawn_shop,
+        update_controls_text, update_settings_text, update_shop_text,
+    },
+    minion::{
+        check_enemy_minion_collision, despawn_minions, enemy_damage_minions,
+        enemy_projectile_damage_minion, issue_minion_commands, spawn_minion_orders_ui,
+        update_minion_orders_ui, update_minions,
+    },
+    phylactery::{
+        check_enemy_phylactery_collision, check_phylactery_destroyed, enemy_damage_phylactery,
+        spawn_phylactery, update_phylactery_health_bar, PHYLACTERY_POSITION,
     },
     player::{
-        display_player_controls, player_move, player_shoot, register_player_damage, spawn_player,
-        spawn_player_ui, switch_active_spell, tick_attack_cooldowns, update_health_bar,
-        update_spell_display,
+        display_player_controls, player_blink, player_move, player_shoot, reform_player,
+        regenerate_mana, register_player_damage, spawn_player, spawn_player_ui,
+        switch_active_spell, tick_attack_cooldowns, tick_invulnerability, update_charge_meter,
+        update_controls_hint, update_health_bar, update_mana_bar, update_spell_display,
+    },
+    projectile::{
+        check_projectile_collision, despawn_spent_projectiles, update_lightning_bolt,
+        update_lightning_storm, update_player_projectiles,
+    },
+    settings::{
+        apply_settings, limit_frame_rate, save_settings, window_descriptor, SETTINGS_CONFIG,
     },
-    projectile::{check_projectile_collision, update_lightning_bolt},
 };
-use bevy::{prelude::*, render::render_resource::TextureUsages};
+use bevy::{
+    input::InputSystem, log::LogPlugin, prelude::*, render::render_resource::TextureUsages,
+    ui::UiSystem,
+};
 use bevy_asset_loader::AssetLoader;
 use bevy_ecs_tilemap::prelude::*;
 use bevy_kira_audio::AudioPlugin;
 use heron::prelude::*;
+use itertools::Itertools;
 
 pub struct GameSetup;
 
 impl Plugin for GameSetup {
     fn build(&self, app: &mut App) {
+        // Logging comes up first, so anything wrong with the config files gets reported
+        app.add_plugin(LogPlugin);
+
         AssetLoader::new(GameState::AssetLoading)
             .continue_to_state(GameState::MainMenu)
             .with_collection::<GameSprites>()
@@ -39,20 +81,29 @@ impl Plugin for GameSetup {
             .with_collection::<GameAudio>()
             .build(app);
 
+        let settings = load_config::<Settings>(SETTINGS_CONFIG);
+
         app.add_state(GameState::AssetLoading)
             .insert_resource(Msaa { samples: 1 })
-            .insert_resource(WindowDescriptor {
-                title: "Power UnLicheted".to_string(),
-                width: SCREEN_WIDTH,
-                height: SCREEN_HEIGHT,
-                resizable: false,
-                ..Default::default()
-            })
+            .insert_resource(window_descriptor(
+                &settings,
+                WindowDescriptor {
+                    title: "Power UnLicheted".to_string(),
+                    width: SCREEN_WIDTH,
+                    height: SCREEN_HEIGHT,
+                    resizable: false,
+                    ..Default::default()
+                },
+            ))
+            .insert_resource(settings)
+            .insert_resource(ColorPalette::default())
+            .insert_resource(ScreenShake::default())
             .insert_resource(ClearColor(Color::rgb(0.04, 0.04, 0.04)))
             .insert_resource(EnemyMorale {
                 current: 50.0,
                 change: 0.0,
                 enemies_killed: 0,
+                minion_kills: 0,
             })
             .insert_resource(WaveManager {
                 active_waves: 0,
@@ -62,18 +113,43 @@ impl Plugin for GameSetup {
             .insert_resource(CurrentDay {
                 day: 0,
                 player_damaged: 0.0,
+                deaths: 0,
             })
             .insert_resource(NarrationViewed(false))
+            .insert_resource(RunState::default())
+            .insert_resource(DayReport::default())
+            .insert_resource(GameRules::default())
+            .insert_resource(ActiveGamepad::default())
+            .insert_resource(InputDevice::default())
+            .insert_resource(load_config::<InputMap>(CONTROLS_CONFIG))
+            .init_resource::<Input<Action>>()
+            .insert_resource(PlayerAim::default())
+            .insert_resource(MenuFocus::default())
+            .insert_resource(MinionOrders::default())
             .insert_resource(CurrentTime(Timer::from_seconds(60.0, false)))
-            .add_plugins(DefaultPlugins)
+            .add_plugins_with(DefaultPlugins, |group| group.disable::<LogPlugin>())
             .add_plugin(PhysicsPlugin::default())
             .add_plugin(TilemapPlugin)
             .add_plugin(AudioPlugin)
             .add_event::<DamagePlayerEvent>()
             .add_event::<EndDayEvent>()
             .add_event::<ChangeSpellEvent>()
+            .add_event::<MinionCommandEvent>()
+            .add_event::<DamageEnemyEvent>()
+            .add_event::<PlayerAttackHitEvent>()
+            .add_event::<EnemyContactEvent>()
+            .add_event::<EnemyAttackHitEvent>()
             .add_startup_system(setup_camera)
+            .add_startup_system(generate_reticle_texture)
             .add_system(set_texture_filters_to_nearest)
+            .add_system(handle_gamepad_connections)
+            .add_system(detect_input_device)
+            .add_system(save_input_map)
+            .add_system(apply_settings)
+            .add_system(save_settings)
+            .add_system_to_stage(CoreStage::Last, limit_frame_rate)
+            .add_system_to_stage(CoreStage::PreUpdate, update_action_state.after(InputSystem))
+            .add_system_to_stage(CoreStage::PreUpdate, navigate_menu.after(UiSystem::Focus))
             .add_system_set(
                 SystemSet::on_enter(GameState::MainMenu)
                     .with_system(spawn_main_menu)
@@ -90,43 +166,86 @@ impl Plugin for GameSetup {
                 SystemSet::on_enter(GameState::MoraleStatus).with_system(spawn_morale_status),
             )
             .add_system_set(
-                SystemSet::on_update(GameState::MoraleStatus).with_system(button_start_day),
+                SystemSet::on_update(GameState::MoraleStatus)
+                    .with_system(button_start_day)
+                    .with_system(animate_spell_unlock),
             )
             .add_system_set(SystemSet::on_exit(GameState::MoraleStatus).with_system(despawn_menu))
+            .add_system_set(SystemSet::on_enter(GameState::Shop).with_system(spawn_shop))
+            .add_system_set(
+                SystemSet::on_update(GameState::Shop)
+                    .with_system(button_shop)
+                    .with_system(update_shop_text),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Shop).with_system(despawn_menu))
             .add_system_set(
                 SystemSet::on_enter(GameState::ActiveGame)
                     .with_system(reset_timer)
                     .with_system(spawn_player)
                     .with_system(spawn_player_ui)
                     .with_system(setup_ui)
+                    .with_system(spawn_minimap)
+                    .with_system(spawn_spell_bar)
+                    .with_system(spawn_reticle)
+                    .with_system(spawn_minion_orders_ui)
+                    .with_system(spawn_phylactery)
                     .with_system(spawn_background)
                     .with_system(display_player_controls),
             )
+            .add_system_set(
+                SystemSet::on_update(GameState::ActiveGame)
+                    .with_system(update_player_aim)
+                    .with_system(update_enemy_paths)
+                    .label(Label::Input),
+            )
             .add_system_set(
                 SystemSet::on_update(GameState::ActiveGame)
                     .with_system(player_move)
+                    .with_system(player_blink)
                     .with_system(player_shoot)
                     .with_system(spawn_enemy_wave)
                     .with_system(update_enemy)
                     .with_system(update_timer)
                     .with_system(update_lightning_bolt)
+                    .with_system(update_lightning_storm)
+                    .with_system(update_player_projectiles)
                     .with_system(update_enemy_shoot)
-                    .label(Label::Movement),
+                    .with_system(issue_minion_commands)
+                    .with_system(update_minions)
+                    .with_system(zoom_camera)
+                    .label(Label::Movement)
+                    .after(Label::Input),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::ActiveGame)
+                    .with_system(route_collisions)
+                    .label(Label::CollisionRouting)
+                    .after(Label::Movement),
             )
             .add_system_set(
                 SystemSet::on_update(GameState::ActiveGame)
                     .with_system(check_enemy_player_collision)
                     .with_system(check_projectile_collision)
+                    .with_system(check_enemy_minion_collision)
+                    .with_system(check_enemy_phylactery_collision)
                     .with_system(switch_active_spell)
                     .label(Label::CollisionCheck)
-                    .after(Label::Movement),
+                    .after(Label::CollisionRouting),
             )
             .add_system_set(
                 SystemSet::on_update(GameState::ActiveGame)
                     .with_system(enemy_damage_player)
                     .with_system(register_player_damage)
                     .with_system(tick_attack_cooldowns)
+                    .with_system(regenerate_mana)
+                    .with_system(tick_invulnerability)
+                    .with_system(reform_player)
+                    .with_system(update_status_effects)
+                    .with_system(apply_enemy_damage)
                     .with_system(enemy_projectile_damage_player)
+                    .with_system(enemy_damage_minions)
+                    .with_system(enemy_projectile_damage_minion)
+                    .with_system(enemy_damage_phylactery)
                     .label(Label::HealthUpdate)
                     .after(Label::CollisionCheck),
             )
@@ -134,24 +253,73 @@ impl Plugin for GameSetup {
                 SystemSet::on_update(GameState::ActiveGame)
                     .with_system(check_despawn)
                     .with_system(despawn_enemies)
+                    .with_system(despawn_minions)
+                    .with_system(despawn_spent_projectiles)
+                    .with_system(check_phylactery_destroyed)
                     .label(Label::Despawn)
                     .after(Label::HealthUpdate),
             )
+            // Pausing waits until every system that may end the day has had its say
+            .add_system_set(
+                SystemSet::on_update(GameState::ActiveGame)
+                    .with_system(pause_game)
+                    .after(Label::Despawn),
+            )
             .add_system_set(
                 SystemSet::on_update(GameState::ActiveGame)
                     .with_system(update_health_bar)
+                    .with_system(update_mana_bar)
+                    .with_system(update_charge_meter)
                     .with_system(update_spell_display)
                     .with_system(update_enemy_render)
                     .with_system(update_ui)
                     .with_system(animate_sprites)
                     .with_system(check_invis)
+                    .with_system(shake_on_player_damage.before(Label::Camera))
+                    .with_system(follow_player.label(Label::Camera))
+                    .with_system(update_offscreen_indicators)
+                    .with_system(update_minimap)
+                    .with_system(update_spell_bar)
+                    .with_system(update_reticle)
+                    .with_system(update_phylactery_health_bar)
+                    .with_system(update_minion_orders_ui)
+                    .with_system(update_controls_hint)
                     .label(Label::UpdateSprites)
                     .after(Label::Despawn),
             )
-            .add_system_set(SystemSet::on_exit(GameState::ActiveGame).with_system(despawn_all))
+            .add_system_set(
+                SystemSet::on_exit(GameState::ActiveGame)
+                    .with_system(settle_day_end)
+                    .with_system(despawn_all)
+                    .with_system(reset_camera),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(spawn_pause_menu))
+            .add_system_set(SystemSet::on_update(GameState::Paused).with_system(button_pause))
+            .add_system_set(
+                SystemSet::on_exit(GameState::Paused)
+                    .with_system(despawn_menu_ui)
+                    .with_system(resume_physics),
+            )
+            // Settings are pushed over the pause menu, which makes way for them until they're closed
+            .add_system_set(SystemSet::on_pause(GameState::Paused).with_system(despawn_menu_ui))
+            .add_system_set(SystemSet::on_resume(GameState::Paused).with_system(spawn_pause_menu))
             .add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(spawn_game_over))
             .add_system_set(SystemSet::on_update(GameState::GameOver).with_system(button_game_over))
             .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(despawn_menu))
+            .add_system_set(SystemSet::on_enter(GameState::Controls).with_system(spawn_controls))
+            .add_system_set(
+                SystemSet::on_update(GameState::Controls)
+                    .with_system(button_controls)
+                    .with_system(update_controls_text),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Controls).with_system(despawn_menu))
+            .add_system_set(SystemSet::on_enter(GameState::Settings).with_system(spawn_settings))
+            .add_system_set(
+                SystemSet::on_update(GameState::Settings)
+                    .with_system(button_settings)
+                    .with_system(update_settings_text),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Settings).with_system(despawn_menu_ui))
             .add_system_set(SystemSet::on_enter(GameState::Credits).with_system(spawn_credits))
             .add_system_set(
                 SystemSet::on_update(GameState::Credits).with_system(button_credits_back),
@@ -175,19 +343,32 @@ fn set_texture_filters_to_nearest(
     }
 }
 
-fn spawn_background(mut commands: Commands, sprites: Res<GameSprites>, mut map_query: MapQuery) {
+fn spawn_background(
+    mut commands: Commands,
+    sprites: Res<GameSprites>,
+    mut map_query: MapQuery,
+    run_state: Res<RunState>,
+    current_day: Res<CurrentDay>,
+) {
+    let layout = generate_arena(
+        run_state.day_seed(current_day.day),
+        (WORLD_WIDTH / ARENA_TILE_SIZE).ceil() as usize,
+        (WORLD_HEIGHT / ARENA_TILE_SIZE).ceil() as usize,
+        &SPAWN_EDGES,
+    );
+
     let map_entity = commands.spawn().id();
     let mut map = Map::new(0u16, map_entity);
 
     let (mut layer_builder, _) = LayerBuilder::new(
         &mut commands,
         LayerSettings::new(
-            MapSize(2, 2),
-            ChunkSize(
-                (SCREEN_WIDTH / 256.0).ceil() as u32,
-                (SCREEN_HEIGHT / 256.0).ceil() as u32,
+            MapSize(
+                (layout.width as f32 / 8.0).ceil() as u32,
+                (layout.height as f32 / 8.0).ceil() as u32,
             ),
-            TileSize(64.0, 64.0),
+            ChunkSize(8, 8),
+            TileSize(ARENA_TILE_SIZE, ARENA_TILE_SIZE),
             TextureSize(64.0, 64.0),
         ),
         0u16,
@@ -195,25 +376,29 @@ fn spawn_background(mut commands: Commands, sprites: Res<GameSprites>, mut map_q
     );
 
     layer_builder.set_all(TileBundle::default());
+    for (x, y) in (0..layout.width).cartesian_product(0..layout.height) {
+        let _ = layer_builder.set_tile(
+            TilePos(x as u32, y as u32),
+            Tile {
+                color: layout.get(x, y).color(),
+                ..Default::default()
+            }
+            .into(),
+        );
+    }
     let layer_entity = map_query.build_layer(&mut commands, layer_builder, sprites.grass.clone());
 
     map.add_layer(&mut commands, 0u16, layer_entity);
 
+    let origin = layout.origin();
     commands
         .entity(map_entity)
         .insert(map)
-        .insert(
-            Transform::from_xyz(-SCREEN_WIDTH / 2.0, -SCREEN_HEIGHT / 2.0, 0.0)
-                .with_scale(Vec3::new(2.0, 2.0, 0.0)),
-        )
+        .insert(Transform::from_xyz(origin.x, origin.y, 0.0))
         .insert(GlobalTransform::default());
-}
 
-fn setup_camera(mut commands: Commands) {
-    commands
-        .spawn_bundle(OrthographicCameraBundle::new_2d())
-        .insert(MainCamera);
-    commands.spawn_bundle(UiCameraBundle::default());
+    commands.insert_resource(EnemyPaths::new(&layout, PHYLACTERY_POSITION.truncate()));
+    commands.insert_resource(layout);
 }
 
 fn setup_ui(mut commands: Commands, fonts: Res<GameFonts>, current_day: Res<CurrentDay>) {
@@ -305,6 +490,9 @@ fn despawn_all(
                 With<InGameUI>,
                 With<DamagesEnemy>,
                 With<RigidBody>,
+                With<Corpse>,
+                With<LightningStorm>,
+                With<Minion>,
             )>,
             Without<Parent>,
         ),